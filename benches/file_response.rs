@@ -0,0 +1,80 @@
+// File response throughput over a loopback TCP connection.
+// Run with `cargo bench --bench file_response`.
+
+use {
+    std::{
+        env, fs,
+        io::{self, Read, Write},
+        net, thread,
+        time::Instant,
+    },
+    hermitcrab::http::Res,
+};
+
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+const ITERATIONS: u32 = 20;
+
+// Accepts one connection and keeps it drained so the sender never stalls on a full socket buffer.
+fn connect_drained() -> (net::TcpStream, thread::JoinHandle<u64>) {
+    let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let drain = thread::spawn(move || {
+        let mut client = net::TcpStream::connect(addr).unwrap();
+        let mut buf = vec![0u8; 256 * 1024];
+        let mut total = 0u64;
+        loop {
+            match client.read(&mut buf) {
+                Ok(0) | Err(_) => return total,
+                Ok(n) => total += n as u64,
+            }
+        }
+    });
+    let (s, _) = listener.accept().unwrap();
+    (s, drain)
+}
+
+fn bench<F: FnMut(&net::TcpStream)>(name: &str, mut f: F) {
+    let (s, drain) = connect_drained();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(&s);
+    }
+    let elapsed = start.elapsed();
+    drop(s);
+    let received = drain.join().unwrap();
+    assert!(received >= FILE_SIZE as u64 * u64::from(ITERATIONS));
+    let mib = (FILE_SIZE as f64 * f64::from(ITERATIONS)) / (1024.0 * 1024.0);
+    println!("{:<28} {:>8.2} ms/response {:>10.1} MiB/s", name, elapsed.as_secs_f64() * 1000.0 / f64::from(ITERATIONS), mib / elapsed.as_secs_f64());
+}
+
+fn main() {
+    let path = env::temp_dir().join(format!("hermitcrab-bench-{}.bin", std::process::id()));
+    {
+        let mut f = fs::File::create(&path).unwrap();
+        let chunk: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+        for _ in 0..FILE_SIZE / chunk.len() {
+            f.write_all(&chunk).unwrap();
+        }
+    }
+    println!("{} MiB file, {} responses each", FILE_SIZE / (1024 * 1024), ITERATIONS);
+
+    bench("read into Vec + respond", |s| {
+        let data = fs::read(&path).unwrap();
+        let mut w = io::BufWriter::new(s);
+        Res::new(&mut w).respond(&data).unwrap();
+    });
+
+    bench("Res::file buffered copy", |s| {
+        let mut w = io::BufWriter::new(s);
+        Res::new(&mut w).file(&path).unwrap();
+    });
+
+    bench("Res::file sendfile", |s| {
+        let mut w = io::BufWriter::new(s);
+        let mut res = Res::new(&mut w);
+        res.set_raw_stream(s);
+        res.file(&path).unwrap();
+    });
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,86 @@
+// Request head parsing throughput on header-heavy requests.
+// Run with `cargo bench --bench parser`.
+
+use {
+    std::{
+        collections::HashMap,
+        hint::black_box,
+        io::{self, BufRead},
+        time::{Duration, Instant},
+    },
+    hermitcrab::http::{Limits, Req},
+};
+
+const ITERATIONS: u32 = 50_000;
+
+fn header_heavy_request() -> String {
+    let mut raw = String::from("GET /api/v1/items?page=2&sort=desc HTTP/1.1\r\nHost: bench.example.com\r\n");
+    for i in 0..40 {
+        raw.push_str(&format!("X-Custom-Header-{}: some-moderately-long-header-value-{}\r\n", i, i));
+    }
+    raw.push_str("Cookie: sid=0123456789abcdef; theme=dark; lang=en\r\n\r\n");
+    raw
+}
+
+// The line-by-line approach the parser replaced: one String per line and a HashMap of owned headers.
+fn legacy_parse(s: &mut dyn BufRead) -> io::Result<(String, HashMap<String, String>)> {
+    let mut line = String::new();
+    s.read_line(&mut line)?;
+    let request_line = line.trim_end().to_string();
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        s.read_line(&mut line)?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let mut kv = line.splitn(2, ':');
+        let k = kv.next().unwrap_or("").trim().to_string();
+        let v = kv.next().unwrap_or("").trim().to_string();
+        headers.insert(k, v);
+    }
+    Ok((request_line, headers))
+}
+
+fn bench<F: FnMut(&[u8])>(name: &str, raw: &[u8], mut f: F) {
+    for _ in 0..ITERATIONS / 10 {
+        f(raw);
+    }
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f(raw);
+    }
+    report(name, raw.len(), start.elapsed());
+}
+
+fn report(name: &str, len: usize, elapsed: Duration) {
+    let per_req = elapsed.as_nanos() as f64 / f64::from(ITERATIONS);
+    let mb_per_s = (len as f64 * f64::from(ITERATIONS)) / elapsed.as_secs_f64() / (1024.0 * 1024.0);
+    println!("{:<28} {:>10.0} ns/req {:>10.1} MiB/s", name, per_req, mb_per_s);
+}
+
+fn main() {
+    let raw = header_heavy_request();
+    let raw = raw.as_bytes();
+    let limits = Limits::default();
+    println!("{} byte request head, {} iterations", raw.len(), ITERATIONS);
+
+    bench("legacy line-by-line", raw, |raw| {
+        let mut s = raw;
+        black_box(legacy_parse(&mut s).unwrap());
+    });
+
+    bench("Req::with_limits", raw, |raw| {
+        let mut s = raw;
+        let req = Req::with_limits(&mut s, &limits).unwrap();
+        black_box(req.header("Cookie"));
+    });
+
+    let mut head_buf = Vec::with_capacity(4096);
+    bench("Req::with_buffer (reused)", raw, |raw| {
+        let mut s = raw;
+        let req = Req::with_buffer(&mut s, &mut head_buf, &limits).unwrap();
+        black_box(req.header("Cookie"));
+    });
+}
@@ -0,0 +1,162 @@
+use {
+    std::{fs, io, path, time},
+    super::{
+        date,
+        headers::*,
+        json::JsonValue,
+        req::Req,
+        res::Res,
+        url,
+    },
+};
+
+/// A file or directory shown in a directory listing.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<time::SystemTime>,
+}
+
+impl Entry {
+    /// Reads size and modification time of `p`, following links.
+    pub fn from_path(name: String, p: &path::Path) -> io::Result<Self> {
+        let meta = fs::metadata(p)?;
+        Ok(Self {
+            name,
+            is_dir: meta.is_dir(),
+            size: if meta.is_dir() { 0 } else { meta.len() },
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+/// The content of a directory, rendered as an HTML page or a JSON array.
+#[derive(Clone, Debug)]
+pub struct Listing {
+    path: String,
+    entries: Vec<Entry>,
+    parent: bool,
+}
+
+impl Listing {
+    /// `path` is the decoded request path of the directory. `parent` adds a link to `../`.
+    /// Directories are listed first, each group by name.
+    pub fn new(path: &str, mut entries: Vec<Entry>, parent: bool) -> Self {
+        entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+        Self { path: path.to_string(), entries, parent }
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = format!("Index of {}", escape_html(&self.path));
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n<table>\n<tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n",
+            title,
+        );
+        if self.parent {
+            html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+        }
+        for entry in self.entries.iter() {
+            let slash = if entry.is_dir { "/" } else { "" };
+            html.push_str(&format!(
+                "<tr><td><a href=\"./{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+                escape_html(&url::percent_encode(&entry.name)),
+                slash,
+                escape_html(&entry.name),
+                slash,
+                if entry.is_dir { String::from("-") } else { fmt_size(entry.size) },
+                entry.modified.map(date::fmt_http_date).unwrap_or_default(),
+            ));
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// `[{"name": .., "type": "file" | "directory", "size": .., "modified": ..}]`, where directories
+    /// have no size and `modified` is an HTTP date or `null`.
+    pub fn to_json(&self) -> JsonValue {
+        JsonValue::Array(self.entries.iter().map(|entry| JsonValue::Object(vec![
+            (String::from("name"), JsonValue::String(entry.name.clone())),
+            (String::from("type"), JsonValue::String(String::from(if entry.is_dir { "directory" } else { "file" }))),
+            (String::from("size"), if entry.is_dir { JsonValue::Null } else { JsonValue::Number(entry.size as f64) }),
+            (String::from("modified"), entry.modified.map(|m| JsonValue::String(date::fmt_http_date(m))).unwrap_or(JsonValue::Null)),
+        ])).collect())
+    }
+
+    /// Responds with JSON if the request's `Accept` asks for it and not for HTML, with HTML otherwise.
+    /// The response depends on `Accept`, so it should be sent with `Vary: Accept`.
+    pub fn respond(&self, req: &Req, res: &mut Res) -> io::Result<()> {
+        if wants_json(req) {
+            res.json_value(&self.to_json())
+        } else {
+            res.html(&self.to_html())
+        }
+    }
+}
+
+fn wants_json(req: &Req) -> bool {
+    let accept = req.header(HTTP_HEADER_ACCEPT).unwrap_or("").to_ascii_lowercase();
+    accept.contains("application/json") && !accept.contains("text/html")
+}
+
+/// Escapes `&`, `<`, `>`, `"` and `'` for use in HTML text and attribute values.
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn fmt_size(n: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if n < 1024 {
+        return n.to_string();
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_escaped_listing() {
+        let modified = time::UNIX_EPOCH + time::Duration::from_secs(784_111_777);
+        let listing = Listing::new("/a&b/", vec![
+            Entry { name: String::from("<script>.txt"), is_dir: false, size: 2048, modified: Some(modified) },
+            Entry { name: String::from("sub \"dir\""), is_dir: true, size: 0, modified: None },
+        ], true);
+        assert_eq!(listing.entries()[0].name, "sub \"dir\"");
+        let html = listing.to_html();
+        assert!(html.contains("<title>Index of /a&amp;b/</title>"));
+        assert!(html.contains("<a href=\"../\">../</a>"));
+        assert!(html.contains("<a href=\"./sub%20%22dir%22/\">sub &quot;dir&quot;/</a></td><td>-</td>"));
+        assert!(html.contains("<a href=\"./%3Cscript%3E.txt\">&lt;script&gt;.txt</a></td><td>2.0K</td><td>Sun, 06 Nov 1994 08:49:37 GMT</td>"));
+        assert!(!html.contains("<script>"));
+        assert_eq!(
+            listing.to_json().to_string(),
+            "[{\"name\":\"sub \\\"dir\\\"\",\"type\":\"directory\",\"size\":null,\"modified\":null},{\"name\":\"<script>.txt\",\"type\":\"file\",\"size\":2048,\"modified\":\"Sun, 06 Nov 1994 08:49:37 GMT\"}]",
+        );
+        assert_eq!(fmt_size(1023), "1023");
+        assert_eq!(fmt_size(5 * 1024 * 1024), "5.0M");
+    }
+}
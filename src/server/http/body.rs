@@ -0,0 +1,135 @@
+use std::io;
+
+const HTTP_100_CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+
+/// Reader over a request body, bounded by the request's `Content-Length`.
+pub struct Body<'r> {
+    inner: &'r mut dyn io::BufRead,
+    remaining: &'r mut u64,
+    // writer for the interim `100 Continue` and the flag recording whether it is still owed
+    continue_writer: Option<(&'r mut dyn io::Write, &'r mut bool)>,
+}
+
+impl<'r> Body<'r> {
+    pub(crate) fn new(inner: &'r mut dyn io::BufRead, remaining: &'r mut u64) -> Self {
+        Self {
+            inner,
+            remaining,
+            continue_writer: None,
+        }
+    }
+
+    pub(crate) fn with_continue(mut self, w: &'r mut dyn io::Write, pending: &'r mut bool) -> Self {
+        self.continue_writer = Some((w, pending));
+        self
+    }
+
+    fn send_continue(&mut self) -> io::Result<()> {
+        if let Some((w, pending)) = self.continue_writer.take() {
+            if *pending {
+                w.write_all(HTTP_100_CONTINUE)?;
+                w.flush()?;
+                *pending = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Bytes of the body that have not been read yet.
+    pub fn remaining(&self) -> u64 {
+        *self.remaining
+    }
+
+    /// Reads the rest of the body, failing if it is longer than `limit` bytes.
+    pub fn read_to_end_limited(&mut self, limit: u64) -> io::Result<Vec<u8>> {
+        if *self.remaining > limit {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request body exceeds size limit"));
+        }
+        let mut data = Vec::with_capacity(*self.remaining as usize);
+        io::Read::read_to_end(self, &mut data)?;
+        if *self.remaining != 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "request body shorter than Content-Length"));
+        }
+        Ok(data)
+    }
+}
+
+impl<'r> io::Read for Body<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = io::BufRead::fill_buf(self)?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+impl<'r> io::BufRead for Body<'r> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if *self.remaining == 0 {
+            return Ok(&[]);
+        }
+        self.send_continue()?;
+        let available = self.inner.fill_buf()?;
+        let n = available.len().min(*self.remaining as usize);
+        Ok(&available[..n])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(*self.remaining as usize);
+        self.inner.consume(amt);
+        *self.remaining -= amt as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::io::Read,
+    };
+
+    #[test]
+    fn stops_at_content_length() {
+        let mut src = io::BufReader::new("Hello world".as_bytes());
+        let mut remaining = 5u64;
+        let mut body = Body::new(&mut src, &mut remaining);
+        let mut out = String::new();
+        body.read_to_string(&mut out).unwrap();
+        assert_eq!(out, "Hello");
+        assert_eq!(remaining, 0);
+    }
+
+    #[test]
+    fn rejects_body_over_limit() {
+        let mut src = io::BufReader::new("Hello world".as_bytes());
+        let mut remaining = 11u64;
+        let mut body = Body::new(&mut src, &mut remaining);
+        assert!(body.read_to_end_limited(4).is_err());
+        assert_eq!(body.read_to_end_limited(11).unwrap(), b"Hello world");
+    }
+
+    #[test]
+    fn sends_continue_before_first_read() {
+        let mut src = io::BufReader::new("Hello".as_bytes());
+        let mut remaining = 5u64;
+        let mut interim: Vec<u8> = vec![];
+        let mut pending = true;
+        {
+            let mut body = Body::new(&mut src, &mut remaining).with_continue(&mut interim, &mut pending);
+            let mut first = [0u8; 2];
+            body.read_exact(&mut first).unwrap();
+            body.read_exact(&mut first).unwrap();
+        }
+        assert_eq!(interim.as_slice(), HTTP_100_CONTINUE);
+        assert!(!pending);
+    }
+
+    #[test]
+    fn reports_truncated_body() {
+        let mut src = io::BufReader::new("Hi".as_bytes());
+        let mut remaining = 5u64;
+        let mut body = Body::new(&mut src, &mut remaining);
+        assert_eq!(body.read_to_end_limited(10).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,166 @@
+use {
+    std::{fmt, path, time},
+    super::glob::{self, Glob},
+};
+
+/// A `Cache-Control` response policy.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct CacheControl {
+    max_age: Option<u64>,
+    public: bool,
+    private: bool,
+    immutable: bool,
+    no_cache: bool,
+    no_store: bool,
+    must_revalidate: bool,
+}
+
+impl CacheControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caches for `secs` seconds.
+    pub fn max_age(secs: u64) -> Self {
+        Self { max_age: Some(secs), ..Self::default() }
+    }
+
+    /// A year-long, public, `immutable` policy for assets whose name changes with their content.
+    pub fn immutable_asset() -> Self {
+        Self::max_age(365 * 24 * 60 * 60).public().immutable()
+    }
+
+    /// Caches but revalidates on every use, e.g. for `index.html` that points at hashed assets.
+    pub fn no_cache() -> Self {
+        Self { no_cache: true, ..Self::default() }
+    }
+
+    pub fn no_store() -> Self {
+        Self { no_store: true, ..Self::default() }
+    }
+
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self.private = false;
+        self
+    }
+
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self.public = false;
+        self
+    }
+
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    pub fn max_age_secs(&self) -> Option<u64> {
+        self.max_age
+    }
+
+    /// The `Expires` time for a response sent at `now`, for HTTP/1.0 caches that ignore `max-age`.
+    /// `no-cache` and `no-store` responses are already expired.
+    pub fn expires(&self, now: time::SystemTime) -> Option<time::SystemTime> {
+        if self.no_cache || self.no_store {
+            return Some(time::UNIX_EPOCH);
+        }
+        self.max_age.map(|secs| now + time::Duration::from_secs(secs))
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut directives: Vec<String> = vec![];
+        if self.public {
+            directives.push("public".to_string());
+        }
+        if self.private {
+            directives.push("private".to_string());
+        }
+        if self.no_store {
+            directives.push("no-store".to_string());
+        }
+        if self.no_cache {
+            directives.push("no-cache".to_string());
+        }
+        if let Some(secs) = self.max_age {
+            directives.push(format!("max-age={}", secs));
+        }
+        if self.must_revalidate {
+            directives.push("must-revalidate".to_string());
+        }
+        if self.immutable {
+            directives.push("immutable".to_string());
+        }
+        write!(f, "{}", directives.join(", "))
+    }
+}
+
+/// A `CacheControl` for files matching a glob.
+#[derive(Clone, Debug)]
+pub struct CacheRule {
+    glob: Glob,
+    policy: CacheControl,
+}
+
+impl CacheRule {
+    /// `pattern` is matched against the path relative to the served directory, see `Glob::new`.
+    pub fn new(pattern: &str, policy: CacheControl) -> Self {
+        Self { glob: Glob::new(pattern), policy }
+    }
+
+    /// A rule for every file with the extension `ext`.
+    pub fn extension(ext: &str, policy: CacheControl) -> Self {
+        Self::new(&format!("*.{}", ext.trim_start_matches('.')), policy)
+    }
+
+    pub fn policy(&self) -> &CacheControl {
+        &self.policy
+    }
+
+    /// `relative` uses `/` as separator, without a leading one.
+    pub fn matches(&self, relative: &str) -> bool {
+        self.glob.matches(relative)
+    }
+}
+
+/// The policy of the first rule matching `p`, a path relative to the served directory.
+pub fn find_policy<'r>(rules: &'r [CacheRule], p: &path::Path) -> Option<&'r CacheControl> {
+    let relative = glob::to_relative(p);
+    rules.iter().find(|r| r.matches(&relative)).map(|r| &r.policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_directives() {
+        assert_eq!(CacheControl::immutable_asset().to_string(), "public, max-age=31536000, immutable");
+        assert_eq!(CacheControl::no_cache().to_string(), "no-cache");
+        assert_eq!(CacheControl::max_age(60).private().must_revalidate().to_string(), "private, max-age=60, must-revalidate");
+        let now = time::UNIX_EPOCH + time::Duration::from_secs(1000);
+        assert_eq!(CacheControl::max_age(60).expires(now), Some(now + time::Duration::from_secs(60)));
+        assert_eq!(CacheControl::no_store().expires(now), Some(time::UNIX_EPOCH));
+        assert_eq!(CacheControl::new().public().expires(now), None);
+    }
+
+    #[test]
+    fn finds_first_matching_rule() {
+        let rules = vec![
+            CacheRule::new("index.html", CacheControl::no_cache()),
+            CacheRule::new("**", CacheControl::max_age(60)),
+        ];
+        assert_eq!(find_policy(&rules, path::Path::new("a/index.html")), Some(&CacheControl::no_cache()));
+        assert_eq!(find_policy(&rules, path::Path::new("a/b.css")), Some(&CacheControl::max_age(60)));
+        assert_eq!(find_policy(&rules[..1], path::Path::new("a/b.css")), None);
+        assert!(CacheRule::extension(".woff2", CacheControl::new()).matches("fonts/a.woff2"));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{error, fmt, net, str};
+
+/// A block of IP addresses such as `10.0.0.0/8` or `fd00::/8`. A bare address is a block of one.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Cidr {
+    addr: net::IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// `None` if `prefix` is longer than the address.
+    pub fn new(addr: net::IpAddr, prefix: u8) -> Option<Self> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return None;
+        }
+        Some(Self { addr, prefix })
+    }
+
+    /// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`) match IPv4 blocks.
+    pub fn contains(&self, ip: net::IpAddr) -> bool {
+        let ip = match ip {
+            net::IpAddr::V6(v6) => v6.to_ipv4_mapped().map(net::IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (net::IpAddr::V4(net), net::IpAddr::V4(ip)) => {
+                prefix_matches(u128::from(u32::from(net)) << 96, u128::from(u32::from(ip)) << 96, self.prefix)
+            },
+            (net::IpAddr::V6(net), net::IpAddr::V6(ip)) => prefix_matches(u128::from(net), u128::from(ip), self.prefix),
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: u128, ip: u128, prefix: u8) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let mask = u128::MAX << (128 - u32::from(prefix));
+    net & mask == ip & mask
+}
+
+/// True if any block in `list` contains `ip`.
+pub fn any_contains(list: &[Cidr], ip: net::IpAddr) -> bool {
+    list.iter().any(|c| c.contains(ip))
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct CidrError(String);
+
+impl fmt::Display for CidrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CidrError: invalid CIDR {:?}", self.0)
+    }
+}
+
+impl error::Error for CidrError {}
+
+impl str::FromStr for Cidr {
+    type Err = CidrError;
+
+    fn from_str(s: &str) -> Result<Self, CidrError> {
+        let err = || CidrError(s.to_string());
+        let (addr, prefix) = match s.find('/') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let addr: net::IpAddr = addr.parse().map_err(|_| err())?;
+        let prefix = match prefix {
+            Some(p) if !p.is_empty() && p.bytes().all(|b| b.is_ascii_digit()) => p.parse::<u8>().map_err(|_| err())?,
+            Some(_) => return Err(err()),
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Cidr::new(addr, prefix).ok_or_else(err)
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> net::IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_matches() {
+        let private: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(private.contains(ip("10.1.2.3")));
+        assert!(private.contains(ip("::ffff:10.1.2.3")));
+        assert!(!private.contains(ip("11.0.0.1")));
+
+        let single: Cidr = "192.168.1.7".parse().unwrap();
+        assert_eq!(single.to_string(), "192.168.1.7/32");
+        assert!(single.contains(ip("192.168.1.7")));
+        assert!(!single.contains(ip("192.168.1.8")));
+
+        let v6: Cidr = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("fe80::1")));
+        assert!(!v6.contains(ip("10.0.0.1")));
+
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains(ip("8.8.8.8")));
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        for s in &["10.0.0.0/33", "10.0.0.0/", "10.0.0.0/+8", "fd00::/129", "example.com", ""] {
+            assert!(s.parse::<Cidr>().is_err(), "{} was accepted", s);
+        }
+    }
+}
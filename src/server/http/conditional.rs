@@ -0,0 +1,264 @@
+use {
+    std::{fmt, io, time},
+    super::{
+        date,
+        headers::*,
+        method::Method,
+        req::Req,
+        res::Res,
+        status::StatusCode,
+    },
+};
+
+/// An entity tag as sent in `ETag` and matched against `If-Match`/`If-None-Match`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ETag {
+    weak: bool,
+    tag: String,
+}
+
+impl ETag {
+    /// `tag` must not contain `"`.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self { weak: false, tag: tag.into() }
+    }
+
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self { weak: true, tag: tag.into() }
+    }
+
+    /// A strong tag derived from the content itself (64-bit FNV-1a).
+    pub fn from_content(data: &[u8]) -> Self {
+        Self::from_hash(fnv1a(data))
+    }
+
+    pub(crate) fn from_hash(hash: u64) -> Self {
+        Self::strong(format!("{:016x}", hash))
+    }
+
+    /// A weak tag derived from size and modification time, which is cheap but cannot tell apart
+    /// two versions written within the same timestamp resolution.
+    pub fn from_metadata(len: u64, modified: time::SystemTime) -> Self {
+        let since_epoch = modified.duration_since(time::UNIX_EPOCH).unwrap_or_default();
+        Self::weak(format!("{:x}-{:x}.{:x}", len, since_epoch.as_secs(), since_epoch.subsec_nanos()))
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    /// A variant of this tag for another representation of the same resource, e.g. a compressed one.
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        Self { weak: self.weak, tag: format!("{}-{}", self.tag, suffix) }
+    }
+
+    /// Parses a single entity tag, `"xyz"` or `W/"xyz"`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(Self { weak, tag: tag.to_string() })
+    }
+
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            write!(f, "W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+    fnv1a_extend(FNV_OFFSET, data)
+}
+
+/// Continues an FNV-1a hash over more data, for hashing a stream in chunks.
+pub(crate) fn fnv1a_extend(mut hash: u64, data: &[u8]) -> u64 {
+    for b in data {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// The outcome of evaluating a request's preconditions.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Precondition {
+    Proceed,
+    NotModified,
+    Failed,
+}
+
+/// Validators of the current representation of a resource.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Validators {
+    pub etag: Option<ETag>,
+    pub last_modified: Option<time::SystemTime>,
+}
+
+impl Validators {
+    pub fn new(etag: Option<ETag>, last_modified: Option<time::SystemTime>) -> Self {
+        Self { etag, last_modified }
+    }
+
+    /// Evaluates `If-Match`, `If-Unmodified-Since`, `If-None-Match` and `If-Modified-Since`
+    /// in the order RFC 9110 13.2.2 gives.
+    pub fn evaluate(&self, req: &Req) -> Precondition {
+        let safe = *req.method() == Method::GET;
+        match req.header(HTTP_HEADER_IF_MATCH) {
+            Some(v) => {
+                if !self.list_matches(v, ETag::strong_eq) {
+                    return Precondition::Failed;
+                }
+            },
+            None => {
+                let since = req.header(HTTP_HEADER_IF_UNMODIFIED_SINCE).and_then(date::parse_http_date);
+                if let (Some(since), Some(modified)) = (since, self.last_modified) {
+                    if truncate(modified) > since {
+                        return Precondition::Failed;
+                    }
+                }
+            },
+        }
+        match req.header(HTTP_HEADER_IF_NONE_MATCH) {
+            Some(v) if self.list_matches(v, ETag::weak_eq) => {
+                return if safe { Precondition::NotModified } else { Precondition::Failed };
+            },
+            Some(_) => {},
+            None if safe => {
+                let since = req.header(HTTP_HEADER_IF_MODIFIED_SINCE).and_then(date::parse_http_date);
+                if let (Some(since), Some(modified)) = (since, self.last_modified) {
+                    if truncate(modified) <= since {
+                        return Precondition::NotModified;
+                    }
+                }
+            },
+            None => {},
+        }
+        Precondition::Proceed
+    }
+
+    // `*` matches any current representation; unparsable members match nothing.
+    fn list_matches(&self, list: &str, eq: fn(&ETag, &ETag) -> bool) -> bool {
+        let etag = match &self.etag {
+            Some(etag) => etag,
+            None => return false,
+        };
+        if list.trim() == "*" {
+            return true;
+        }
+        list.split(',').filter_map(ETag::parse).any(|candidate| eq(&candidate, etag))
+    }
+
+    /// Sets `ETag` and `Last-Modified` on `res`.
+    pub fn set_headers(&self, res: &mut Res) {
+        if let Some(etag) = &self.etag {
+            res.set_header(HTTP_HEADER_ETAG, &etag.to_string());
+        }
+        if let Some(modified) = self.last_modified {
+            res.set_header(HTTP_HEADER_LAST_MODIFIED, &date::fmt_http_date(modified));
+        }
+    }
+
+    /// Sets the validator headers and, if the preconditions say so, answers with `304` or `412`.
+    /// Returns whether it responded, in which case the handler is done.
+    pub fn respond_if_unchanged(&self, req: &Req, res: &mut Res) -> io::Result<bool> {
+        self.set_headers(res);
+        match self.evaluate(req) {
+            Precondition::Proceed => Ok(false),
+            Precondition::NotModified => res.empty(StatusCode::NOT_MODIFIED).map(|_| true),
+            Precondition::Failed => res.empty(StatusCode::PRECONDITION_FAILED).map(|_| true),
+        }
+    }
+}
+
+// HTTP dates have whole seconds
+pub(crate) fn truncate(t: time::SystemTime) -> time::SystemTime {
+    let secs = t.duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    time::UNIX_EPOCH + time::Duration::from_secs(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(validators: &Validators, method: &str, headers: &str) -> Precondition {
+        let raw = format!("{} / HTTP/1.1\r\n{}\r\n", method, headers);
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let req = Req::new(&mut buf).unwrap();
+        validators.evaluate(&req)
+    }
+
+    fn validators() -> Validators {
+        Validators::new(Some(ETag::strong("abc")), Some(time::UNIX_EPOCH + time::Duration::from_millis(784_111_777_500)))
+    }
+
+    #[test]
+    fn parses_and_compares_etags() {
+        assert_eq!(ETag::parse("W/\"x\""), Some(ETag::weak("x")));
+        assert_eq!(ETag::parse(" \"x\" "), Some(ETag::strong("x")));
+        assert_eq!(ETag::parse("x"), None);
+        assert_eq!(ETag::weak("x").to_string(), "W/\"x\"");
+        assert!(ETag::weak("x").weak_eq(&ETag::strong("x")));
+        assert!(!ETag::weak("x").strong_eq(&ETag::strong("x")));
+        assert_eq!(ETag::from_content(b"hello"), ETag::from_content(b"hello"));
+        assert_ne!(ETag::from_content(b"hello"), ETag::from_content(b"hellp"));
+    }
+
+    #[test]
+    fn evaluates_preconditions_in_order() {
+        let v = validators();
+        assert_eq!(evaluate(&v, "GET", ""), Precondition::Proceed);
+        assert_eq!(evaluate(&v, "GET", "If-None-Match: \"x\", W/\"abc\"\r\n"), Precondition::NotModified);
+        assert_eq!(evaluate(&v, "GET", "If-None-Match: *\r\n"), Precondition::NotModified);
+        assert_eq!(evaluate(&v, "PUT", "If-None-Match: \"abc\"\r\n"), Precondition::Failed);
+        assert_eq!(evaluate(&v, "GET", "If-None-Match: \"x\"\r\n"), Precondition::Proceed);
+        // If-None-Match takes precedence over If-Modified-Since
+        assert_eq!(evaluate(&v, "GET", "If-None-Match: \"x\"\r\nIf-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"), Precondition::Proceed);
+        assert_eq!(evaluate(&v, "GET", "If-Modified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"), Precondition::NotModified);
+        assert_eq!(evaluate(&v, "GET", "If-Modified-Since: Sun, 06 Nov 1994 08:49:36 GMT\r\n"), Precondition::Proceed);
+        assert_eq!(evaluate(&v, "GET", "If-Modified-Since: garbage\r\n"), Precondition::Proceed);
+        assert_eq!(evaluate(&v, "PUT", "If-Match: \"abc\"\r\n"), Precondition::Proceed);
+        assert_eq!(evaluate(&v, "PUT", "If-Match: W/\"abc\"\r\n"), Precondition::Failed);
+        assert_eq!(evaluate(&v, "PUT", "If-Unmodified-Since: Sun, 06 Nov 1994 08:49:36 GMT\r\n"), Precondition::Failed);
+        assert_eq!(evaluate(&v, "PUT", "If-Unmodified-Since: Sun, 06 Nov 1994 08:49:37 GMT\r\n"), Precondition::Proceed);
+        assert_eq!(evaluate(&Validators::default(), "PUT", "If-Match: *\r\n"), Precondition::Failed);
+    }
+
+    #[test]
+    fn responds_not_modified_with_validators() {
+        let raw = "GET / HTTP/1.1\r\nIf-None-Match: \"abc\"\r\n\r\n";
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let req = Req::new(&mut buf).unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        assert!(validators().respond_if_unchanged(&req, &mut res).unwrap());
+        let written = String::from_utf8(write_buf).unwrap();
+        assert!(written.starts_with("HTTP/1.x 304 Not Modified\r\n"));
+        assert!(written.contains("ETag: \"abc\"\r\n"));
+        assert!(written.contains("Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+        assert!(!written.contains("Content-Length"));
+    }
+}
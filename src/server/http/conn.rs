@@ -0,0 +1,53 @@
+use std::{
+    net,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// What is known about the connection a request arrived on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnInfo {
+    /// Unique for the lifetime of the process, starting from 1. `0` when the request was not read from a socket.
+    pub id: u64,
+    pub peer_addr: Option<net::SocketAddr>,
+    pub local_addr: Option<net::SocketAddr>,
+    /// The client address reported by a trusted PROXY protocol header.
+    pub proxied_addr: Option<net::SocketAddr>,
+    /// How many requests have been read from this connection, including the current one.
+    pub request_count: u64,
+    pub tls: bool,
+}
+
+impl ConnInfo {
+    /// Describes a freshly accepted plain TCP connection and assigns it the next id.
+    pub fn from_stream(s: &net::TcpStream) -> Self {
+        Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            peer_addr: s.peer_addr().ok(),
+            local_addr: s.local_addr().ok(),
+            proxied_addr: None,
+            request_count: 0,
+            tls: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_stream() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (s, peer) = listener.accept().unwrap();
+        let first = ConnInfo::from_stream(&s);
+        let second = ConnInfo::from_stream(&s);
+        assert!(first.id > 0 && second.id > first.id);
+        assert_eq!(first.peer_addr, Some(peer));
+        assert_eq!(first.peer_addr, client.local_addr().ok());
+        assert_eq!(first.local_addr, listener.local_addr().ok());
+        assert!(!first.tls);
+    }
+}
@@ -0,0 +1,248 @@
+use {
+    std::{error, fmt, time},
+    super::date,
+};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+/// A cookie to be sent with a `Set-Cookie` response header.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    expires: Option<time::SystemTime>,
+    max_age: Option<time::Duration>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            expires: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn expires(mut self, expires: time::SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: time::Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Whether a browser would store `other` in place of this cookie: same name, path and domain.
+    pub fn replaces(&self, other: &Cookie) -> bool {
+        self.name == other.name && self.path == other.path && self.domain == other.domain
+    }
+
+    /// Checks the cookie can go into a `Set-Cookie` header as it is, per RFC 6265: the name a token,
+    /// the value without controls, whitespace, `"`, `,`, `;` or `\` (or all of it in double quotes),
+    /// and `Path` and `Domain` without controls or `;`.
+    pub fn validate(&self) -> Result<(), CookieError> {
+        if self.name.is_empty() || !self.name.bytes().all(is_token_byte) {
+            return Err(CookieError::InvalidName(self.name.clone()));
+        }
+        let value = self.value.as_bytes();
+        let unquoted = if value.len() >= 2 && value[0] == b'"' && value[value.len() - 1] == b'"' { &value[1..value.len() - 1] } else { value };
+        if !unquoted.iter().all(|b| is_cookie_octet(*b)) {
+            return Err(CookieError::InvalidValue(self.value.clone()));
+        }
+        for (attribute, v) in [("Path", &self.path), ("Domain", &self.domain)].iter() {
+            if let Some(v) = v {
+                if !v.bytes().all(|b| (0x20..0x7f).contains(&b) && b != b';') {
+                    return Err(CookieError::InvalidAttribute(attribute, v.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_token_byte(b: u8) -> bool {
+    (0x21..0x7f).contains(&b) && !b"()<>@,;:\\\"/[]?={}".contains(&b)
+}
+
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+/// Why a cookie can't be sent.
+#[derive(Clone, PartialEq, Debug)]
+pub enum CookieError {
+    InvalidName(String),
+    InvalidValue(String),
+    InvalidAttribute(&'static str, String),
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CookieError::InvalidName(name) => write!(f, "CookieError::InvalidName: {:?}", name),
+            CookieError::InvalidValue(value) => write!(f, "CookieError::InvalidValue: {:?}", value),
+            CookieError::InvalidAttribute(attribute, value) => write!(f, "CookieError::InvalidAttribute: {}={:?}", attribute, value),
+        }
+    }
+}
+
+impl error::Error for CookieError {}
+
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", date::fmt_http_date(expires))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the value of a `Cookie` request header into name/value pairs.
+/// Pairs without a name are skipped and surrounding double quotes are stripped from values.
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header.split(';')
+        .filter_map(|pair| {
+            let mut kv = pair.splitn(2, '=');
+            let name = kv.next()?.trim();
+            if name.is_empty() {
+                return None;
+            }
+            let mut value = kv.next().unwrap_or("").trim();
+            if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                value = &value[1..value.len() - 1];
+            }
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cookie_header() {
+        let cookies = parse_cookie_header("sid=abc123; theme=\"dark\";; =orphan; empty=");
+        assert_eq!(cookies, vec![
+            ("sid".to_string(), "abc123".to_string()),
+            ("theme".to_string(), "dark".to_string()),
+            ("empty".to_string(), "".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn formats_set_cookie() {
+        let c = Cookie::new("sid", "abc")
+            .path("/")
+            .domain("xiwen.com")
+            .expires(time::UNIX_EPOCH + time::Duration::from_secs(784_111_777))
+            .max_age(time::Duration::from_secs(3600))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            c.to_string(),
+            "sid=abc; Path=/; Domain=xiwen.com; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=3600; Secure; HttpOnly; SameSite=Lax",
+        );
+        assert_eq!(Cookie::new("a", "b").to_string(), "a=b");
+    }
+
+    #[test]
+    fn validates_cookies() {
+        assert!(Cookie::new("sid", "abc-123_=/").path("/app").domain("xiwen.com").validate().is_ok());
+        assert!(Cookie::new("q", "\"quoted\"").validate().is_ok());
+        assert_eq!(Cookie::new("", "a").validate(), Err(CookieError::InvalidName(String::new())));
+        assert_eq!(Cookie::new("s id", "a").validate(), Err(CookieError::InvalidName(String::from("s id"))));
+        assert!(Cookie::new("a;b", "a").validate().is_err());
+        for value in ["a\r\nSet-Cookie: x=y", "a; Domain=evil.com", "a,b", "a b", "\"", "a\\b", "caf\u{e9}"].iter() {
+            assert_eq!(Cookie::new("sid", *value).validate(), Err(CookieError::InvalidValue(value.to_string())), "{}", value);
+        }
+        assert_eq!(
+            Cookie::new("sid", "a").path("/; HttpOnly").validate(),
+            Err(CookieError::InvalidAttribute("Path", String::from("/; HttpOnly"))),
+        );
+        assert!(Cookie::new("sid", "a").domain("x.com\r\nX: y").validate().is_err());
+        assert!(Cookie::new("sid", "a").path("/").replaces(&Cookie::new("sid", "b").path("/")));
+        assert!(!Cookie::new("sid", "a").path("/").replaces(&Cookie::new("sid", "b").path("/admin")));
+        assert!(!Cookie::new("sid", "a").replaces(&Cookie::new("sid", "b").domain("xiwen.com")));
+    }
+}
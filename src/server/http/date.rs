@@ -0,0 +1,155 @@
+use std::{cell::RefCell, time};
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTH_NAMES: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// Formats a time as an IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+/// Times before the unix epoch are clamped to the epoch.
+pub fn fmt_http_date(t: time::SystemTime) -> String {
+    let secs = t.duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAY_NAMES[(days % 7) as usize],
+        day,
+        MONTH_NAMES[(month - 1) as usize],
+        year,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+    )
+}
+
+thread_local! {
+    // (unix seconds, formatted date) of the last call on this thread
+    static NOW: RefCell<(u64, String)> = const { RefCell::new((u64::MAX, String::new())) };
+}
+
+/// The current time as an IMF-fixdate. It is formatted at most once per second on each thread.
+pub fn http_date_now() -> String {
+    let now = time::SystemTime::now();
+    let secs = now.duration_since(time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    NOW.with(|cached| {
+        let mut cached = cached.borrow_mut();
+        if cached.0 != secs {
+            *cached = (secs, fmt_http_date(now));
+        }
+        cached.1.clone()
+    })
+}
+
+/// Parses an HTTP-date in any of the three formats RFC 9110 requires recipients to accept:
+/// IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`), RFC 850 (`Sunday, 06-Nov-94 08:49:37 GMT`)
+/// and asctime (`Sun Nov  6 08:49:37 1994`). Dates before the unix epoch give `None`.
+pub fn parse_http_date(s: &str) -> Option<time::SystemTime> {
+    let s = s.trim();
+    let (day, month, year, clock) = if let Some(i) = s.find(", ") {
+        let rest = &s[i + 2..];
+        let parts: Vec<&str> = rest.split(' ').collect();
+        match parts.as_slice() {
+            [day, month, year, clock, "GMT"] if day.len() == 2 && year.len() == 4 => (*day, *month, year.parse::<i64>().ok()?, *clock),
+            [date, clock, "GMT"] => {
+                let date: Vec<&str> = date.split('-').collect();
+                match date.as_slice() {
+                    [day, month, year] if day.len() == 2 && year.len() == 2 => {
+                        // RFC 9110 5.6.7: two digit years more than 50 years in the future are in the past century
+                        let yy = year.parse::<i64>().ok()?;
+                        (*day, *month, if yy < 70 { 2000 + yy } else { 1900 + yy }, *clock)
+                    },
+                    _ => return None,
+                }
+            },
+            _ => return None,
+        }
+    } else {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            [_, month, day, clock, year] if year.len() == 4 => (*day, *month, year.parse::<i64>().ok()?, *clock),
+            _ => return None,
+        }
+    };
+    if !day.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let day: u32 = day.parse().ok()?;
+    let month = MONTH_NAMES.iter().position(|m| *m == month)? as u32 + 1;
+    let clock: Vec<u64> = clock.split(':').map(|c| if c.len() == 2 { c.parse().ok() } else { None }).collect::<Option<_>>()?;
+    let (h, m, sec) = match clock.as_slice() {
+        [h, m, s] if *h < 24 && *m < 60 && *s < 61 => (*h, *m, *s),
+        _ => return None,
+    };
+    if day == 0 || day > 31 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    Some(time::UNIX_EPOCH + time::Duration::from_secs(days as u64 * 86400 + h * 3600 + m * 60 + sec))
+}
+
+// Howard Hinnant's days-from-civil algorithm.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+// Howard Hinnant's days-to-civil algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_imf_fixdate() {
+        let t = time::UNIX_EPOCH + time::Duration::from_secs(784_111_777);
+        assert_eq!(fmt_http_date(t), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(fmt_http_date(time::UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn formats_leap_day() {
+        let t = time::UNIX_EPOCH + time::Duration::from_secs(951_825_600);
+        assert_eq!(fmt_http_date(t), "Tue, 29 Feb 2000 12:00:00 GMT");
+    }
+
+    #[test]
+    fn parses_all_http_date_formats() {
+        let t = Some(time::UNIX_EPOCH + time::Duration::from_secs(784_111_777));
+        assert_eq!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"), t);
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), t);
+        assert_eq!(parse_http_date("Sun Nov  6 08:49:37 1994"), t);
+        let leap = time::UNIX_EPOCH + time::Duration::from_secs(951_825_600);
+        assert_eq!(parse_http_date(&fmt_http_date(leap)), Some(leap));
+        for bad in &["", "Sun, 06 Nov 1994 08:49:37 UTC", "Sun, 6 Nov 1994 08:49:37 GMT", "Sun, 06 Foo 1994 08:49:37 GMT", "Sun, 06 Nov 1994 24:00:00 GMT", "yesterday"] {
+            assert_eq!(parse_http_date(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn caches_current_date() {
+        let first = http_date_now();
+        assert!(first.ends_with(" GMT"));
+        assert_eq!(first.len(), "Sun, 06 Nov 1994 08:49:37 GMT".len());
+        NOW.with(|cached| assert_eq!(cached.borrow().1, first));
+    }
+}
@@ -0,0 +1,174 @@
+use {
+    std::{borrow, fs, io, path, sync, time},
+    super::serve_static::{MemoryFile, MemoryTree},
+};
+
+/// A directory compiled into the executable, served with `Muxer::serve_embedded`.
+#[derive(Copy, Clone, Debug)]
+pub struct EmbeddedDir {
+    files: &'static [(&'static str, &'static [u8], u64)],
+}
+
+impl EmbeddedDir {
+    /// `files` are `(path, content, modified)`: the path relative to the directory with `/` as separator,
+    /// the modification time in seconds since the unix epoch, or 0 if unknown. `generate` writes such a list.
+    pub const fn new(files: &'static [(&'static str, &'static [u8], u64)]) -> Self {
+        Self { files }
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &'static str> {
+        self.files.iter().map(|(relative, _, _)| *relative)
+    }
+
+    /// The content of the file at `relative`.
+    pub fn get(&self, relative: &str) -> Option<&'static [u8]> {
+        self.files.iter().find(|(p, _, _)| *p == relative).map(|(_, data, _)| *data)
+    }
+
+    pub(crate) fn to_tree(self) -> MemoryTree {
+        self.files.iter()
+            .map(|(relative, data, modified)| {
+                let modified = if *modified == 0 { None } else { Some(time::UNIX_EPOCH + time::Duration::from_secs(*modified)) };
+                (relative.to_string(), sync::Arc::new(MemoryFile::new(borrow::Cow::Borrowed(*data), modified)))
+            })
+            .collect()
+    }
+}
+
+/// Expands to the `EmbeddedDir` that `embed::generate` wrote to `$file` under `OUT_DIR`.
+#[macro_export]
+macro_rules! include_embedded {
+    ($file:expr) => {
+        $crate::http::embed::EmbeddedDir::new(include!(concat!(env!("OUT_DIR"), "/", $file)))
+    };
+}
+
+/// Writes the files under `dir` to `out` as a list `include_embedded!` compiles in, for a build script:
+///
+/// ```ignore
+/// // build.rs
+/// fn main() {
+///     let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("assets.rs");
+///     hermitcrab::http::embed::generate("assets", out).unwrap();
+/// }
+///
+/// // main.rs
+/// static ASSETS: hermitcrab::http::EmbeddedDir = hermitcrab::include_embedded!("assets.rs");
+/// server.serve_embedded("/", &ASSETS, hermitcrab::http::StaticOptions::new()).unwrap();
+/// ```
+///
+/// Hidden files and names that aren't utf8 are left out, and cargo reruns the script when `dir` changes.
+pub fn generate(dir: impl AsRef<path::Path>, out: impl AsRef<path::Path>) -> io::Result<()> {
+    // the list is included from `OUT_DIR`, so `include_bytes!` needs absolute paths
+    let dir = fs::canonicalize(dir)?;
+    let mut files = vec![];
+    collect(&dir, "", &mut files)?;
+    let mut code = String::from("&[\n");
+    for (relative, p, modified) in files.iter() {
+        code.push_str(&format!("    ({:?}, include_bytes!({:?}) as &[u8], {}),\n", relative, p, modified));
+    }
+    code.push_str("]\n");
+    fs::write(out, code)?;
+    println!("cargo:rerun-if-changed={}", dir.display());
+    Ok(())
+}
+
+// `(relative path, absolute path, modified)` for every file under `dir`, in name order.
+fn collect(dir: &path::Path, relative: &str, files: &mut Vec<(String, String, u64)>) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<fs::DirEntry>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let (name, absolute) = match (entry.file_name().into_string(), path.to_str()) {
+            (Ok(name), Some(absolute)) if !name.starts_with('.') => (name, absolute.to_string()),
+            _ => continue,
+        };
+        let relative = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        let meta = fs::metadata(&path)?;
+        if meta.is_dir() {
+            collect(&path, &relative, files)?;
+        } else {
+            let modified = meta.modified().ok()
+                .and_then(|m| m.duration_since(time::UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            files.push((relative, absolute, modified));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        super::super::{Handle, Muxer, Req, Res, StaticOptions},
+    };
+
+    static ASSETS: EmbeddedDir = EmbeddedDir::new(&[
+        ("index.html", include_bytes!("../../../test_data/index.html"), 784_111_777),
+        ("assets/main.css", include_bytes!("../../../test_data/assets/main.css"), 0),
+        ("assets/main.css.gz", b"gzipped", 0),
+    ]);
+
+    fn get(mux: &Muxer, target: &str, headers: &str) -> String {
+        let raw = format!("GET {} HTTP/1.1\r\n{}\r\n", target, headers);
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf).unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
+        match mux.get_handler(&mut req) {
+            Some(mut handler) => handler.handle(&mut req, &mut res),
+            None => return String::from("no handler"),
+        }
+        String::from_utf8(write_buf).unwrap()
+    }
+
+    #[test]
+    fn serves_embedded_files() {
+        assert_eq!(ASSETS.paths().collect::<Vec<&str>>(), vec!["index.html", "assets/main.css", "assets/main.css.gz"]);
+        assert_eq!(ASSETS.get("assets/main.css.gz"), Some(&b"gzipped"[..]));
+        let mut mux = Muxer::default();
+        mux.serve_embedded("/site", &ASSETS, StaticOptions::new()).unwrap();
+
+        let res = get(&mux, "/site/", "");
+        assert!(res.starts_with("HTTP/1.x 200 OK\r\n"));
+        assert!(res.contains("Content-Type: text/html; charset=utf-8\r\n"));
+        assert!(res.contains("Last-Modified: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+        assert!(res.ends_with("</html>"));
+        let etag = res.split("\r\n").find(|l| l.starts_with("ETag: ")).unwrap()[6..].to_string();
+        assert!(get(&mux, "/site/index.html", &format!("If-None-Match: {}\r\n", etag)).starts_with("HTTP/1.x 304 Not Modified\r\n"));
+        assert!(get(&mux, "/site", "").contains("Location: /site/\r\n"));
+
+        let res = get(&mux, "/site/assets/main.css", "Accept-Encoding: gzip\r\n");
+        assert!(res.contains("Content-Type: text/css; charset=utf-8\r\n"));
+        assert!(res.contains("Content-Encoding: gzip\r\n"));
+        assert!(res.contains("ETag: \""), "{}", res);
+        assert!(res.ends_with("\r\n\r\ngzipped"));
+        assert!(get(&mux, "/site/assets/main.css", "").ends_with("\r\n\r\n#main-title {\n    color: green;\n}"));
+        assert_eq!(get(&mux, "/site/assets/missing.css", ""), "no handler");
+    }
+
+    #[test]
+    fn generates_include_list() {
+        let out = std::env::temp_dir().join(format!("hermitcrab-embed-{}.rs", std::process::id()));
+        generate("test_data", &out).unwrap();
+        let code = fs::read_to_string(&out).unwrap();
+        let root = fs::canonicalize("test_data").unwrap();
+        let css = format!("    (\"assets/main.css\", include_bytes!({:?}) as &[u8], ", root.join("assets/main.css").to_str().unwrap());
+        assert!(code.starts_with("&[\n"), "{}", code);
+        assert!(code.contains(&css), "{}", code);
+        assert!(code.find("assets/main.css").unwrap() < code.find("\"index.html\"").unwrap());
+        assert!(code.ends_with("]\n"));
+        fs::remove_file(&out).unwrap();
+    }
+}
@@ -0,0 +1,103 @@
+use std::{fmt, path};
+
+/// A content coding static files can be stored precompressed in, as a sibling file with an extra extension.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ContentEncoding {
+    Brotli,
+    Gzip,
+}
+
+impl ContentEncoding {
+    /// In order of preference when a client accepts several equally.
+    pub const ALL: [ContentEncoding; 2] = [ContentEncoding::Brotli, ContentEncoding::Gzip];
+
+    /// The token in `Accept-Encoding` and `Content-Encoding`.
+    pub fn token(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gzip",
+        }
+    }
+
+    /// The extension of a file holding this encoding of another, e.g. `gz` for `app.js.gz`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ContentEncoding::Brotli => "br",
+            ContentEncoding::Gzip => "gz",
+        }
+    }
+
+    /// `p` with this encoding's extension appended.
+    pub fn sidecar(&self, p: &path::Path) -> path::PathBuf {
+        let mut name = p.as_os_str().to_os_string();
+        name.push(".");
+        name.push(self.extension());
+        path::PathBuf::from(name)
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.token())
+    }
+}
+
+// The quality `accept_encoding` gives `token`, from an exact entry or else from `*`; `None` if it names neither.
+fn quality(accept_encoding: &str, token: &str) -> Option<f32> {
+    let mut wildcard = None;
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim();
+        let q = params
+            .filter_map(|p| {
+                let (name, value) = p.split_at(p.find('=')?);
+                if name.trim().eq_ignore_ascii_case("q") { value[1..].trim().parse::<f32>().ok() } else { None }
+            })
+            .next()
+            .unwrap_or(1.0);
+        if coding.eq_ignore_ascii_case(token) || (token == "gzip" && coding.eq_ignore_ascii_case("x-gzip")) {
+            return Some(q);
+        }
+        if coding == "*" {
+            wildcard = Some(q);
+        }
+    }
+    wildcard
+}
+
+/// The encoding out of `available` that `accept_encoding` rates highest, ties going to the earlier one.
+/// `None` if the client accepts none of them, including when it sent no `Accept-Encoding`.
+pub fn negotiate(accept_encoding: Option<&str>, available: &[ContentEncoding]) -> Option<ContentEncoding> {
+    let accept_encoding = accept_encoding?;
+    let mut best: Option<(ContentEncoding, f32)> = None;
+    for encoding in available.iter() {
+        let q = quality(accept_encoding, encoding.token()).unwrap_or(0.0);
+        if q > 0.0 && best.map(|(_, best_q)| q > best_q).unwrap_or(true) {
+            best = Some((*encoding, q));
+        }
+    }
+    best.map(|(encoding, _)| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOTH: [ContentEncoding; 2] = ContentEncoding::ALL;
+
+    #[test]
+    fn negotiates_by_quality() {
+        assert_eq!(negotiate(Some("gzip, deflate, br"), &BOTH), Some(ContentEncoding::Brotli));
+        assert_eq!(negotiate(Some("gzip, deflate, br"), &[ContentEncoding::Gzip]), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate(Some("br;q=0.5, gzip"), &BOTH), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate(Some("br;q=0, *"), &BOTH), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate(Some("*;q=0.1"), &BOTH), Some(ContentEncoding::Brotli));
+        assert_eq!(negotiate(Some("X-GZIP"), &BOTH), Some(ContentEncoding::Gzip));
+        assert_eq!(negotiate(Some("deflate, identity"), &BOTH), None);
+        assert_eq!(negotiate(Some("gzip;q=0"), &BOTH), None);
+        assert_eq!(negotiate(Some(""), &BOTH), None);
+        assert_eq!(negotiate(None, &BOTH), None);
+        assert_eq!(negotiate(Some("br"), &[]), None);
+        assert_eq!(ContentEncoding::Gzip.sidecar(path::Path::new("a/app.js")), path::Path::new("a/app.js.gz"));
+    }
+}
@@ -0,0 +1,104 @@
+use {
+    std::{error, fmt, io},
+    super::{limits::LimitError, status::StatusCode},
+};
+
+/// Why a request could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    Timeout,
+    MissingCrlf,
+    InvalidRequestLine(String),
+    UnknownMethod(String),
+    UnsupportedVersion(String),
+    InvalidHeader(String),
+    InvalidContentLength(String),
+    UnsupportedTransferEncoding(String),
+    /// Both `Transfer-Encoding` and `Content-Length`, which peers may frame differently.
+    ConflictingLength,
+    TooLarge(LimitError),
+}
+
+impl ParseError {
+    /// The status to answer with, or `None` when the connection is unusable and should just be closed.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ParseError::Io(_) => None,
+            ParseError::Timeout => Some(StatusCode::REQUEST_TIMEOUT),
+            ParseError::UnknownMethod(_) | ParseError::UnsupportedTransferEncoding(_) => Some(StatusCode::NOT_IMPLEMENTED),
+            ParseError::UnsupportedVersion(_) => Some(StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+            ParseError::TooLarge(e) => Some(e.status()),
+            _ => Some(StatusCode::BAD_REQUEST),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Io(e) => write!(f, "ParseError::Io: {}", e),
+            ParseError::Timeout => write!(f, "ParseError::Timeout"),
+            ParseError::MissingCrlf => write!(f, "ParseError::MissingCrlf"),
+            ParseError::InvalidRequestLine(line) => write!(f, "ParseError::InvalidRequestLine: {:?}", line),
+            ParseError::UnknownMethod(m) => write!(f, "ParseError::UnknownMethod: {:?}", m),
+            ParseError::UnsupportedVersion(v) => write!(f, "ParseError::UnsupportedVersion: {:?}", v),
+            ParseError::InvalidHeader(line) => write!(f, "ParseError::InvalidHeader: {:?}", line),
+            ParseError::InvalidContentLength(v) => write!(f, "ParseError::InvalidContentLength: {:?}", v),
+            ParseError::UnsupportedTransferEncoding(v) => write!(f, "ParseError::UnsupportedTransferEncoding: {:?}", v),
+            ParseError::ConflictingLength => write!(f, "ParseError::ConflictingLength: Transfer-Encoding with Content-Length"),
+            ParseError::TooLarge(e) => write!(f, "ParseError::TooLarge: {}", e),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            // what a read timeout on the socket surfaces as
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => ParseError::Timeout,
+            _ => ParseError::Io(e),
+        }
+    }
+}
+
+impl From<LimitError> for ParseError {
+    fn from(e: LimitError) -> Self {
+        ParseError::TooLarge(e)
+    }
+}
+
+impl From<ParseError> for io::Error {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Io(e) => e,
+            ParseError::Timeout => io::Error::new(io::ErrorKind::TimedOut, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_status() {
+        assert_eq!(ParseError::from(io::Error::new(io::ErrorKind::WouldBlock, "timeout")).status(), Some(StatusCode::REQUEST_TIMEOUT));
+        assert_eq!(ParseError::from(io::Error::new(io::ErrorKind::ConnectionReset, "reset")).status(), None);
+        assert_eq!(ParseError::MissingCrlf.status(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(ParseError::UnknownMethod("BREW".to_string()).status(), Some(StatusCode::NOT_IMPLEMENTED));
+        assert_eq!(ParseError::UnsupportedTransferEncoding("chunked".to_string()).status(), Some(StatusCode::NOT_IMPLEMENTED));
+        assert_eq!(ParseError::ConflictingLength.status(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(ParseError::from(LimitError::TooManyHeaders).status(), Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE));
+    }
+
+    #[test]
+    fn converts_to_io_error() {
+        let e: io::Error = ParseError::MissingCrlf.into();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.get_ref().unwrap().is::<ParseError>());
+    }
+}
@@ -0,0 +1,145 @@
+use {
+    std::{fmt, io, error},
+    super::{url, status::StatusCode},
+};
+
+pub const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+pub const DEFAULT_FORM_SIZE_LIMIT: u64 = 64 * 1024;
+
+#[derive(Debug)]
+pub enum FormError {
+    UnsupportedContentType(Option<String>),
+    TooLarge(u64),
+    Malformed(String),
+    Io(io::Error),
+}
+
+impl FormError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            FormError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            FormError::Malformed(_) | FormError::Io(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormError::UnsupportedContentType(Some(t)) => write!(f, "FormError::UnsupportedContentType: {}", t),
+            FormError::UnsupportedContentType(None) => write!(f, "FormError::UnsupportedContentType: missing Content-Type"),
+            FormError::TooLarge(limit) => write!(f, "FormError::TooLarge: body exceeds {} bytes", limit),
+            FormError::Malformed(msg) => write!(f, "FormError::Malformed: {}", msg),
+            FormError::Io(e) => write!(f, "FormError::Io: {}", e),
+        }
+    }
+}
+
+impl error::Error for FormError {}
+
+impl From<io::Error> for FormError {
+    fn from(e: io::Error) -> Self {
+        FormError::Io(e)
+    }
+}
+
+/// Decoded `application/x-www-form-urlencoded` fields, in the order they were sent.
+/// A name may appear more than once.
+#[derive(Default, Clone, PartialEq, Debug)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+}
+
+impl Form {
+    pub fn parse(data: &[u8]) -> Result<Self, FormError> {
+        let mut form = Form::default();
+        for pair in data.split(|b| *b == b'&') {
+            if pair.is_empty() {
+                continue;
+            }
+            let mut kv = pair.splitn(2, |b| *b == b'=');
+            let name = decode_component(kv.next().unwrap_or(b""))?;
+            let value = decode_component(kv.next().unwrap_or(b""))?;
+            form.fields.push((name, value));
+        }
+        Ok(form)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields.iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields.iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.fields.iter().any(|(k, _)| k == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+fn decode_component(raw: &[u8]) -> Result<String, FormError> {
+    let decoded = url::percent_decode(raw, true)
+        .ok_or_else(|| FormError::Malformed(format!("invalid percent-encoding in \"{}\"", String::from_utf8_lossy(raw))))?;
+    String::from_utf8(decoded)
+        .map_err(|_| FormError::Malformed(format!("\"{}\" is not valid utf8", String::from_utf8_lossy(raw))))
+}
+
+/// Compares the media type of a `Content-Type` value, ignoring parameters and case.
+pub fn is_media_type(content_type: &str, expected: &str) -> bool {
+    content_type.split(';')
+        .next()
+        .map(|t| t.trim().eq_ignore_ascii_case(expected))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_multi_map() {
+        let form = Form::parse(b"name=Xiwen+Li&tag=a&tag=b%26c&&flag").unwrap();
+        assert_eq!(form.len(), 4);
+        assert_eq!(form.get("name"), Some("Xiwen Li"));
+        assert_eq!(form.get_all("tag"), vec!["a", "b&c"]);
+        assert_eq!(form.get("flag"), Some(""));
+        assert!(!form.contains_key("missing"));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        match Form::parse(b"a=%zz") {
+            Err(FormError::Malformed(_)) => {},
+            other => panic!("unexpected: {:?}", other),
+        }
+        match Form::parse(b"a=%ff") {
+            Err(FormError::Malformed(_)) => {},
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn matches_media_type() {
+        assert!(is_media_type("Application/X-WWW-Form-Urlencoded; charset=UTF-8", FORM_CONTENT_TYPE));
+        assert!(!is_media_type("text/plain", FORM_CONTENT_TYPE));
+    }
+}
@@ -0,0 +1,186 @@
+use {
+    std::net,
+    super::cidr::{self, Cidr},
+};
+
+/// What trusted proxies reported about the original request.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Forwarded {
+    pub client_addr: Option<net::SocketAddr>,
+    pub scheme: Option<String>,
+    pub host: Option<String>,
+}
+
+// One proxy hop: the address it received the request from and the scheme/host it saw.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub(crate) struct Hop {
+    addr: Option<net::SocketAddr>,
+    proto: Option<String>,
+    host: Option<String>,
+}
+
+/// Walks `hops` from the nearest proxy outwards while they are trusted. The first untrusted
+/// (or unknown) address is the client; scheme and host come from the hop that reported it.
+/// `None` if `immediate`, the peer that sent the request, is not a trusted proxy.
+pub(crate) fn resolve(immediate: net::IpAddr, hops: &[Hop], trusted: &[Cidr]) -> Option<Forwarded> {
+    if !cidr::any_contains(trusted, immediate) {
+        return None;
+    }
+    let mut client = None;
+    for hop in hops.iter().rev() {
+        client = Some(hop);
+        match hop.addr {
+            Some(a) if cidr::any_contains(trusted, a.ip()) => continue,
+            _ => break,
+        }
+    }
+    client.map(|hop| Forwarded {
+        client_addr: hop.addr,
+        scheme: hop.proto.as_ref().map(|p| p.to_ascii_lowercase()),
+        host: hop.host.clone(),
+    })
+}
+
+/// Parses the values of every `Forwarded` header (RFC 7239) in order. `None` if any is malformed,
+/// in which case the header is ignored as a whole.
+pub(crate) fn parse_forwarded<'v>(values: impl Iterator<Item = &'v str>) -> Option<Vec<Hop>> {
+    let mut hops = vec![];
+    for value in values {
+        for element in split_quoted(value, b',') {
+            let mut hop = Hop::default();
+            for pair in split_quoted(element, b';') {
+                let pair = pair.trim();
+                if pair.is_empty() {
+                    continue;
+                }
+                let eq = pair.find('=')?;
+                let v = unquote(pair[eq + 1..].trim())?;
+                match pair[..eq].trim().to_ascii_lowercase().as_str() {
+                    "for" => hop.addr = parse_node(&v),
+                    "proto" => hop.proto = Some(v),
+                    "host" => hop.host = Some(v),
+                    _ => {},
+                }
+            }
+            hops.push(hop);
+        }
+    }
+    Some(hops)
+}
+
+/// Builds hops from `X-Forwarded-For`, pairing `X-Forwarded-Proto`/`Host` entries with them
+/// when the lists line up, or applying a single entry to every hop.
+pub(crate) fn parse_x_forwarded(xff: &[&str], proto: &[&str], host: &[&str]) -> Vec<Hop> {
+    let split = |values: &[&str]| -> Vec<String> {
+        values.iter().flat_map(|v| v.split(',')).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()
+    };
+    let addrs = split(xff);
+    let protos = split(proto);
+    let hosts = split(host);
+    let pick = |list: &[String], i: usize| match list.len() {
+        1 => Some(list[0].clone()),
+        n if n == addrs.len() => Some(list[i].clone()),
+        _ => None,
+    };
+    addrs.iter().enumerate().map(|(i, a)| Hop {
+        addr: parse_node(a),
+        proto: pick(&protos, i),
+        host: pick(&hosts, i),
+    }).collect()
+}
+
+// `192.0.2.43`, `192.0.2.43:47011`, `[2001:db8::1]:4711`, `2001:db8::1`. Missing ports become 0;
+// `unknown` and obfuscated identifiers give `None`.
+fn parse_node(s: &str) -> Option<net::SocketAddr> {
+    if let Ok(a) = s.parse::<net::SocketAddr>() {
+        return Some(a);
+    }
+    let ip = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')).unwrap_or(s);
+    ip.parse::<net::IpAddr>().ok().map(|ip| net::SocketAddr::new(ip, 0))
+}
+
+// Splits on `sep` outside of quoted strings.
+fn split_quoted(s: &str, sep: u8) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, b) in s.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' if quoted => escaped = true,
+            b'"' => quoted = !quoted,
+            _ if b == sep && !quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn unquote(v: &str) -> Option<String> {
+    if !v.starts_with('"') {
+        return Some(v.to_string());
+    }
+    let inner = v.strip_prefix('"')?.strip_suffix('"')?;
+    let mut out = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        out.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trusted() -> Vec<Cidr> {
+        vec!["10.0.0.0/8".parse().unwrap()]
+    }
+
+    fn ip(s: &str) -> net::IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn addr(s: &str) -> Option<net::SocketAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn parses_forwarded() {
+        let hops = parse_forwarded(vec![
+            "for=192.0.2.60;proto=HTTPS;host=\"example.com\", for=\"[2001:db8:cafe::17]:4711\"",
+            "for=unknown;by=10.0.0.1",
+        ].into_iter()).unwrap();
+        assert_eq!(hops.len(), 3);
+        assert_eq!(hops[0].addr, addr("192.0.2.60:0"));
+        assert_eq!(hops[0].proto.as_deref(), Some("HTTPS"));
+        assert_eq!(hops[0].host.as_deref(), Some("example.com"));
+        assert_eq!(hops[1].addr, addr("[2001:db8:cafe::17]:4711"));
+        assert_eq!(hops[2].addr, None);
+        assert!(parse_forwarded(vec!["for"].into_iter()).is_none());
+        assert!(parse_forwarded(vec!["for=\"192.0.2.60"].into_iter()).is_none());
+    }
+
+    #[test]
+    fn resolves_through_trusted_hops_only() {
+        let hops = parse_x_forwarded(&["6.6.6.6, 192.0.2.1", "10.0.0.2"], &["https"], &["a.example, b.example, c.example"]);
+        let f = resolve(ip("10.0.0.1"), &hops, &trusted()).unwrap();
+        // the spoofed 6.6.6.6 sits behind an untrusted hop and is never reached
+        assert_eq!(f.client_addr, addr("192.0.2.1:0"));
+        assert_eq!(f.scheme.as_deref(), Some("https"));
+        assert_eq!(f.host.as_deref(), Some("b.example"));
+
+        assert_eq!(resolve(ip("192.0.2.9"), &hops, &trusted()), None);
+        assert_eq!(resolve(ip("10.0.0.1"), &[], &trusted()), None);
+
+        let all_trusted = parse_x_forwarded(&["10.1.1.1:80, 10.0.0.2"], &[], &[]);
+        let f = resolve(ip("10.0.0.1"), &all_trusted, &trusted()).unwrap();
+        assert_eq!(f.client_addr, addr("10.1.1.1:80"));
+        assert_eq!(f.scheme, None);
+    }
+}
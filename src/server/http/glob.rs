@@ -0,0 +1,103 @@
+use std::path;
+
+/// A pattern for paths under a served directory.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Glob {
+    pattern: String,
+    // whether the pattern names a path rather than just a file name
+    anchored: bool,
+}
+
+impl Glob {
+    /// `pattern` is matched against the whole relative path, or against the last segment alone
+    /// when it has no `/`, so `index.html` matches in every directory and `/index.html` only at the top.
+    /// `*` matches within a segment, `**` across segments, `?` one character. A trailing `/**`
+    /// matches the directory itself too, so `/api/**` covers `api` as well as everything under it.
+    pub fn new(pattern: &str) -> Self {
+        Self { pattern: pattern.trim_start_matches('/').to_string(), anchored: pattern.contains('/') }
+    }
+
+    /// `relative` uses `/` as separator, without a leading one.
+    pub fn matches(&self, relative: &str) -> bool {
+        if self.anchored {
+            glob_match(self.pattern.as_bytes(), relative.as_bytes())
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(self.pattern.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+/// `p` as `Glob::matches` takes it: `/` separated, without a leading `/` or `.` segments.
+pub fn to_relative(p: &path::Path) -> String {
+    p.components()
+        .filter_map(|c| match c {
+            path::Component::Normal(s) => s.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+// Whether `pattern` matches all of `text`, filling a table of which pattern suffix matches which text
+// suffix back to front, so stacked `*` and `**` cost `pattern.len() * text.len()` at worst.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    let width = text.len() + 1;
+    // matched[i * width + j]: whether `pattern[i..]` matches `text[j..]`
+    let mut matched = vec![false; (pattern.len() + 1) * width];
+    matched[pattern.len() * width + text.len()] = true;
+    for i in (0..pattern.len()).rev() {
+        let is_double_star = pattern[i] == b'*' && pattern.get(i + 1) == Some(&b'*');
+        // `**` matching up to some later position in the text
+        let mut any_later = false;
+        for j in (0..width).rev() {
+            let rest = |k: usize, l: usize| matched[k * width + l];
+            let m = match pattern[i] {
+                b'*' if is_double_star => {
+                    any_later = any_later || rest(i + 2, j);
+                    // `**/` also matches no directory at all
+                    any_later || (pattern.get(i + 2) == Some(&b'/') && rest(i + 3, j))
+                },
+                b'*' => rest(i + 1, j) || (j < text.len() && text[j] != b'/' && matched[i * width + j + 1]),
+                b'?' => j < text.len() && text[j] != b'/' && rest(i + 1, j + 1),
+                // a trailing `/**` also matches the directory itself
+                b'/' if j == text.len() && &pattern[i..] == b"/**" => true,
+                c => j < text.len() && text[j] == c && rest(i + 1, j + 1),
+            };
+            matched[i * width + j] = m;
+        }
+    }
+    matched[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        let glob = |p: &str| Glob::new(p);
+        assert!(glob("*.js").matches("assets/app.3f2a.js"));
+        assert!(!glob("*.js").matches("assets/app.json"));
+        assert!(glob("index.html").matches("docs/index.html"));
+        assert!(glob("/index.html").matches("index.html"));
+        assert!(!glob("/index.html").matches("docs/index.html"));
+        assert!(glob("assets/*.css").matches("assets/main.css"));
+        assert!(!glob("assets/*.css").matches("assets/sub/main.css"));
+        assert!(glob("assets/**/*.css").matches("assets/main.css"));
+        assert!(glob("assets/**/*.css").matches("assets/a/b/main.css"));
+        assert!(glob("**").matches("a/b/c"));
+        assert!(glob("/api/**").matches("api/users/1"));
+        assert!(!glob("/api/**").matches("app/api/users"));
+        assert!(glob("app.????.js").matches("app.3f2a.js"));
+        assert!(glob("/api/**").matches("api"));
+        assert!(!glob("/api/**").matches("apis"));
+        assert!(glob("a/**/b").matches("a/b"));
+        assert!(!glob("a/**/b").matches("a/xb"));
+        assert!(glob("a/**/b").matches("a/x/y/b"));
+        let long = "a/".repeat(2000);
+        assert!(!glob("/**/**/**/**/**/**/x").matches(&long));
+        assert!(!glob("*a*a*a*a*a*a*b").matches(&"a".repeat(2000)));
+        assert_eq!(to_relative(path::Path::new("./a/b.css")), "a/b.css");
+    }
+}
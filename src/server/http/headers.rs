@@ -3,9 +3,36 @@
 
 pub const HTTP_HEADER_CONTENT_LENGTH: &str = "Content-Length";
 pub const HTTP_HEADER_CONTENT_TYPE: &str = "Content-Type";
+pub const HTTP_HEADER_TRANSFER_ENCODING: &str = "Transfer-Encoding";
 pub const HTTP_HEADER_COOKIE: &str = "Cookie";
+pub const HTTP_HEADER_SET_COOKIE: &str = "Set-Cookie";
+pub const HTTP_HEADER_DATE: &str = "Date";
+pub const HTTP_HEADER_SERVER: &str = "Server";
+pub const HTTP_HEADER_LOCATION: &str = "Location";
+pub const HTTP_HEADER_FORWARDED: &str = "Forwarded";
+pub const HTTP_HEADER_X_FORWARDED_FOR: &str = "X-Forwarded-For";
+pub const HTTP_HEADER_X_FORWARDED_PROTO: &str = "X-Forwarded-Proto";
+pub const HTTP_HEADER_X_FORWARDED_HOST: &str = "X-Forwarded-Host";
+pub const HTTP_HEADER_X_CONTENT_TYPE_OPTIONS: &str = "X-Content-Type-Options";
+pub const HTTP_HEADER_ETAG: &str = "ETag";
+pub const HTTP_HEADER_LAST_MODIFIED: &str = "Last-Modified";
+pub const HTTP_HEADER_IF_MATCH: &str = "If-Match";
+pub const HTTP_HEADER_IF_NONE_MATCH: &str = "If-None-Match";
+pub const HTTP_HEADER_IF_MODIFIED_SINCE: &str = "If-Modified-Since";
+pub const HTTP_HEADER_IF_UNMODIFIED_SINCE: &str = "If-Unmodified-Since";
+pub const HTTP_HEADER_CACHE_CONTROL: &str = "Cache-Control";
+pub const HTTP_HEADER_EXPIRES: &str = "Expires";
+pub const HTTP_HEADER_VARY: &str = "Vary";
+pub const HTTP_HEADER_RANGE: &str = "Range";
+pub const HTTP_HEADER_IF_RANGE: &str = "If-Range";
+pub const HTTP_HEADER_ACCEPT_RANGES: &str = "Accept-Ranges";
+pub const HTTP_HEADER_CONTENT_RANGE: &str = "Content-Range";
+pub const HTTP_HEADER_ACCEPT: &str = "Accept";
+pub const HTTP_HEADER_ACCEPT_ENCODING: &str = "Accept-Encoding";
+pub const HTTP_HEADER_CONTENT_ENCODING: &str = "Content-Encoding";
 pub const HTTP_HEADER_HOST: &str = "Host";
 pub const HTTP_HEADER_CONNECTION: &str = "Connection";
+pub const HTTP_HEADER_EXPECT: &str = "Expect";
 pub const HTTP_HEADER_AUTHORIZATION: &str = "Authorization";
 pub const HTTP_HEADER_ORIGIN: &str = "Origin";
 pub const HTTP_HEADER_USER_AGENT: &str = "User-Agent";
@@ -0,0 +1,494 @@
+use {
+    std::{error, fmt, io},
+    super::{res::Res, status::StatusCode},
+};
+
+pub const JSON_CONTENT_TYPE: &str = "application/json";
+pub const DEFAULT_JSON_SIZE_LIMIT: u64 = 1024 * 1024;
+
+const MAX_NESTING_DEPTH: usize = 128;
+
+#[derive(Debug)]
+pub enum JsonError {
+    UnsupportedContentType(Option<String>),
+    TooLarge(u64),
+    Syntax(String),
+    Io(io::Error),
+}
+
+impl JsonError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            JsonError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            JsonError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            JsonError::Syntax(_) | JsonError::Io(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Answers the request with this error's status and a `{"error": "..."}` body.
+    pub fn respond(&self, res: &mut Res) -> io::Result<()> {
+        res.set_status_code(self.status());
+        res.json_value(&JsonValue::Object(vec![
+            ("error".to_string(), JsonValue::String(self.to_string())),
+        ]))
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonError::UnsupportedContentType(Some(t)) => write!(f, "JsonError::UnsupportedContentType: {}", t),
+            JsonError::UnsupportedContentType(None) => write!(f, "JsonError::UnsupportedContentType: missing Content-Type"),
+            JsonError::TooLarge(limit) => write!(f, "JsonError::TooLarge: body exceeds {} bytes", limit),
+            JsonError::Syntax(msg) => write!(f, "JsonError::Syntax: {}", msg),
+            JsonError::Io(e) => write!(f, "JsonError::Io: {}", e),
+        }
+    }
+}
+
+impl error::Error for JsonError {}
+
+impl From<io::Error> for JsonError {
+    fn from(e: io::Error) -> Self {
+        JsonError::Io(e)
+    }
+}
+
+/// Accepts `application/json` as well as structured `+json` media types.
+pub fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    media_type == JSON_CONTENT_TYPE || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+/// A JSON document for handlers that don't use serde. Object members keep their order.
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    pub fn parse(s: &str) -> Result<Self, JsonError> {
+        let mut p = Parser { s: s.as_bytes(), pos: 0 };
+        p.skip_whitespace();
+        let v = p.parse_value(0)?;
+        p.skip_whitespace();
+        if p.pos != p.s.len() {
+            return Err(p.error("trailing characters"));
+        }
+        Ok(v)
+    }
+
+    pub fn parse_bytes(data: &[u8]) -> Result<Self, JsonError> {
+        let s = std::str::from_utf8(data).map_err(|_| JsonError::Syntax("body is not valid utf8".to_string()))?;
+        Self::parse(s)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn index(&self, i: usize) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Array(items) => items.get(i),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            JsonValue::Number(n) if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        *self == JsonValue::Null
+    }
+}
+
+impl From<bool> for JsonValue {
+    fn from(b: bool) -> Self {
+        JsonValue::Bool(b)
+    }
+}
+
+impl From<f64> for JsonValue {
+    fn from(n: f64) -> Self {
+        JsonValue::Number(n)
+    }
+}
+
+impl From<i64> for JsonValue {
+    fn from(n: i64) -> Self {
+        JsonValue::Number(n as f64)
+    }
+}
+
+impl From<&str> for JsonValue {
+    fn from(s: &str) -> Self {
+        JsonValue::String(s.to_string())
+    }
+}
+
+impl From<String> for JsonValue {
+    fn from(s: String) -> Self {
+        JsonValue::String(s)
+    }
+}
+
+impl<T: Into<JsonValue>> From<Vec<T>> for JsonValue {
+    fn from(items: Vec<T>) -> Self {
+        JsonValue::Array(items.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(b) => write!(f, "{}", b),
+            // JSON has no representation for NaN or infinity
+            JsonValue::Number(n) if !n.is_finite() => write!(f, "null"),
+            JsonValue::Number(n) => write!(f, "{}", n),
+            JsonValue::String(s) => write_json_string(f, s),
+            JsonValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            JsonValue::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_json_string(f, k)?;
+                    write!(f, ":{}", v)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+fn write_json_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Parser<'s> {
+    s: &'s [u8],
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, msg: &str) -> JsonError {
+        JsonError::Syntax(format!("{} at byte {}", msg, self.pos))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.s.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str, v: JsonValue) -> Result<JsonValue, JsonError> {
+        if self.s[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(v)
+        } else {
+            Err(self.error("invalid literal"))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        if depth > MAX_NESTING_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        match self.s.get(self.pos) {
+            Some(b'n') => self.expect_literal("null", JsonValue::Null),
+            Some(b't') => self.expect_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.expect_literal("false", JsonValue::Bool(false)),
+            Some(b'"') => self.parse_string().map(JsonValue::String),
+            Some(b'[') => self.parse_array(depth),
+            Some(b'{') => self.parse_object(depth),
+            Some(b'-') | Some(b'0'..=b'9') => self.parse_number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        self.pos += 1;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.s.get(self.pos) == Some(&b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.s.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                },
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        self.pos += 1;
+        let mut members = vec![];
+        self.skip_whitespace();
+        if self.s.get(self.pos) == Some(&b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.s.get(self.pos) != Some(&b'"') {
+                return Err(self.error("expected object key"));
+            }
+            let k = self.parse_string()?;
+            self.skip_whitespace();
+            if self.s.get(self.pos) != Some(&b':') {
+                return Err(self.error("expected ':'"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let v = self.parse_value(depth + 1)?;
+            members.push((k, v));
+            self.skip_whitespace();
+            match self.s.get(self.pos) {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(members));
+                },
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.pos;
+        if self.s.get(self.pos) == Some(&b'-') {
+            self.pos += 1;
+        }
+        match self.s.get(self.pos) {
+            Some(b'0') => self.pos += 1,
+            Some(b'1'..=b'9') => self.skip_digits(),
+            _ => return Err(self.error("invalid number")),
+        }
+        if self.s.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !self.s.get(self.pos).map(u8::is_ascii_digit).unwrap_or(false) {
+                return Err(self.error("invalid number"));
+            }
+            self.skip_digits();
+        }
+        if let Some(b'e') | Some(b'E') = self.s.get(self.pos) {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.s.get(self.pos) {
+                self.pos += 1;
+            }
+            if !self.s.get(self.pos).map(u8::is_ascii_digit).unwrap_or(false) {
+                return Err(self.error("invalid number"));
+            }
+            self.skip_digits();
+        }
+        // the grammar above only admits ascii
+        let text = std::str::from_utf8(&self.s[start..self.pos]).unwrap();
+        text.parse::<f64>()
+            .map(JsonValue::Number)
+            .map_err(|_| self.error("invalid number"))
+    }
+
+    fn skip_digits(&mut self) {
+        while self.s.get(self.pos).map(u8::is_ascii_digit).unwrap_or(false) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(c) = self.s.get(self.pos) {
+                if *c == b'"' || *c == b'\\' || *c < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            // input came from a &str and we only stop on ascii bytes, so this is a char boundary
+            out.push_str(std::str::from_utf8(&self.s[start..self.pos]).unwrap());
+            match self.s.get(self.pos) {
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(out);
+                },
+                Some(b'\\') => {
+                    self.pos += 1;
+                    self.parse_escape(&mut out)?;
+                },
+                Some(_) => return Err(self.error("control character in string")),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self, out: &mut String) -> Result<(), JsonError> {
+        let c = match self.s.get(self.pos) {
+            Some(b'"') => '"',
+            Some(b'\\') => '\\',
+            Some(b'/') => '/',
+            Some(b'b') => '\u{8}',
+            Some(b'f') => '\u{c}',
+            Some(b'n') => '\n',
+            Some(b'r') => '\r',
+            Some(b't') => '\t',
+            Some(b'u') => {
+                self.pos += 1;
+                let hi = self.parse_hex4()?;
+                let code = if (0xD800..0xDC00).contains(&hi) {
+                    if !self.s[self.pos..].starts_with(b"\\u") {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    self.pos += 2;
+                    let lo = self.parse_hex4()?;
+                    if !(0xDC00..0xE000).contains(&lo) {
+                        return Err(self.error("unpaired surrogate"));
+                    }
+                    0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+                } else {
+                    hi
+                };
+                out.push(std::char::from_u32(code).ok_or_else(|| self.error("invalid unicode escape"))?);
+                return Ok(());
+            },
+            _ => return Err(self.error("invalid escape")),
+        };
+        self.pos += 1;
+        out.push(c);
+        Ok(())
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.s.get(self.pos..self.pos + 4).ok_or_else(|| self.error("truncated unicode escape"))?;
+        let text = std::str::from_utf8(digits).map_err(|_| self.error("invalid unicode escape"))?;
+        let code = u32::from_str_radix(text, 16).map_err(|_| self.error("invalid unicode escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_documents() {
+        let v = JsonValue::parse(r#" {"name": "crab", "legs": 10, "tags": ["a", "b\n"], "shell": null, "ok": true, "pi": -3.5e0} "#).unwrap();
+        assert_eq!(v.get("name").and_then(JsonValue::as_str), Some("crab"));
+        assert_eq!(v.get("legs").and_then(JsonValue::as_i64), Some(10));
+        assert_eq!(v.get("tags").and_then(|t| t.index(1)).and_then(JsonValue::as_str), Some("b\n"));
+        assert!(v.get("shell").unwrap().is_null());
+        assert_eq!(v.get("ok").and_then(JsonValue::as_bool), Some(true));
+        assert_eq!(v.get("pi").and_then(JsonValue::as_f64), Some(-3.5));
+    }
+
+    #[test]
+    fn parses_unicode_escapes() {
+        let v = JsonValue::parse(r#""é🦀""#).unwrap();
+        assert_eq!(v.as_str(), Some("é🦀"));
+        assert!(JsonValue::parse(r#""\ud83e""#).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        for doc in &["", "{", "[1,]", "{\"a\" 1}", "01", "1.", "tru", "\"a", "[1] x", "{'a': 1}"] {
+            match JsonValue::parse(doc) {
+                Err(JsonError::Syntax(_)) => {},
+                other => panic!("{:?} parsed as {:?}", doc, other),
+            }
+        }
+        assert!(JsonValue::parse(&"[".repeat(MAX_NESTING_DEPTH + 2)).is_err());
+    }
+
+    #[test]
+    fn serializes_round_trip() {
+        let v = JsonValue::Object(vec![
+            ("msg".to_string(), JsonValue::from("say \"hi\"\u{1}")),
+            ("list".to_string(), JsonValue::from(vec![1i64, 2])),
+            ("half".to_string(), JsonValue::from(0.5)),
+        ]);
+        let s = v.to_string();
+        assert_eq!(s, r#"{"msg":"say \"hi\"\u0001","list":[1,2],"half":0.5}"#);
+        assert_eq!(JsonValue::parse(&s).unwrap(), v);
+    }
+
+    #[test]
+    fn matches_json_content_types() {
+        assert!(is_json_content_type("application/json; charset=utf-8"));
+        assert!(is_json_content_type("application/problem+json"));
+        assert!(!is_json_content_type("text/json"));
+    }
+}
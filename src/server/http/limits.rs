@@ -0,0 +1,60 @@
+use {
+    std::{error, fmt},
+    super::status::StatusCode,
+};
+
+pub const DEFAULT_MAX_REQUEST_LINE: usize = 8192;
+pub const DEFAULT_MAX_HEADER_COUNT: usize = 100;
+pub const DEFAULT_MAX_HEADER_BYTES: usize = 64 * 1024;
+
+/// Bounds applied while reading a request. Exceeding them is answered with
+/// `414`, `431` or `413` instead of reading further.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Limits {
+    pub max_request_line: usize,
+    pub max_header_count: usize,
+    pub max_header_bytes: usize,
+    pub max_body_size: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_request_line: DEFAULT_MAX_REQUEST_LINE,
+            max_header_count: DEFAULT_MAX_HEADER_COUNT,
+            max_header_bytes: DEFAULT_MAX_HEADER_BYTES,
+            max_body_size: None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LimitError {
+    RequestLineTooLong,
+    TooManyHeaders,
+    HeadersTooLarge,
+    BodyTooLarge,
+}
+
+impl LimitError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            LimitError::RequestLineTooLong => StatusCode::URI_TOO_LONG,
+            LimitError::TooManyHeaders | LimitError::HeadersTooLarge => StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            LimitError::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+impl fmt::Display for LimitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitError::RequestLineTooLong => write!(f, "LimitError::RequestLineTooLong"),
+            LimitError::TooManyHeaders => write!(f, "LimitError::TooManyHeaders"),
+            LimitError::HeadersTooLarge => write!(f, "LimitError::HeadersTooLarge"),
+            LimitError::BodyTooLarge => write!(f, "LimitError::BodyTooLarge"),
+        }
+    }
+}
+
+impl error::Error for LimitError {}
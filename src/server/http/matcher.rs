@@ -9,8 +9,13 @@ use {
         res::Res,
         method::Method,
         trie::Trie,
+        error::ParseError,
+        serve_static::{self, StaticDir, StaticOptions, MemoryTree},
+        embed::EmbeddedDir,
+        mime::MimeTypes,
         handler::*,
-    }
+    },
+    crate::logger::help::*,
 };
 
 pub type Matcher = Fn(&mut Req) -> bool + Send + Sync + 'static;
@@ -19,7 +24,14 @@ pub type MatcherRef = sync::Arc<Matcher>;
 #[derive(Clone)]
 pub struct MatchEntry {
     matcher: MatcherRef,
-    handler: HandlerRef,
+    handler: EntryHandler,
+}
+
+#[derive(Clone)]
+enum EntryHandler {
+    Shared(HandlerRef),
+    // made anew for every request, so requests don't wait on each other's lock
+    PerRequest(sync::Arc<dyn Fn() -> HandlerRef + Send + Sync>),
 }
 
 
@@ -27,9 +39,26 @@ pub struct MatchEntry {
 pub struct Muxer {
     filters: Vec<MatchEntry>,
     trie: Trie,
+    parse_error_handler: Option<ErrorHandlerRef>,
+    mime_types: MimeTypes,
 }
 
 impl Muxer {
+    /// Lets the application fill in the response to a request that could not be parsed.
+    /// The status is already set; the handler may override it and respond with its own body.
+    pub fn on_parse_error(&mut self, h: impl Fn(&ParseError, &mut Res) + Send + Sync + 'static) {
+        self.parse_error_handler = Some(sync::Arc::new(h));
+    }
+
+    pub fn parse_error_handler(&self) -> Option<ErrorHandlerRef> {
+        self.parse_error_handler.clone()
+    }
+
+    /// Content type overrides used by every static mount that does not bring its own.
+    pub fn mime_types(&self) -> &MimeTypes {
+        &self.mime_types
+    }
+
     pub fn filter(&mut self, m: impl Fn(&mut Req) -> bool + Send + Sync + 'static) -> MatchChain {
         let matcher: MatcherRef = sync::Arc::new(m);
         MatchChain {
@@ -47,7 +76,7 @@ impl Muxer {
         }
         self.filters.push(MatchEntry{
             matcher: m,
-            handler: handler,
+            handler: EntryHandler::Shared(handler),
         })
     }
 
@@ -62,19 +91,71 @@ impl Muxer {
         } else {
             for m in self.filters.iter() {
                 if (m.matcher)(req) {
-                    return Some(m.handler.clone());
+                    return Some(match &m.handler {
+                        EntryHandler::Shared(handler) => handler.clone(),
+                        EntryHandler::PerRequest(make) => make(),
+                    });
                 }
             }
             None
         }
     }
 
+    /// Serves a single-page application from `dir_path` at `prefix`: paths that match no file get `fallback`,
+    /// e.g. `index.html`, except for those that look like missing assets. See `StaticOptions::spa`.
+    pub fn serve_spa(&mut self, prefix: &str, dir_path: &str, fallback: &str) -> io::Result<()> {
+        self.serve_static_with(prefix, dir_path, StaticOptions::default().spa(fallback))
+    }
+
+    /// Serves the files under `dir_path` at `prefix`, read from disk per request.
     pub fn serve_static(&mut self, prefix: &str, dir_path: &str) -> io::Result<()> {
-        super::serve_static::add_directory_to_trie(
-            path::Path::new(prefix),
-            path::Path::new(dir_path),
-            &mut self.trie,
-        )
+        self.serve_static_with(prefix, dir_path, StaticOptions::default())
+    }
+
+    pub fn serve_static_with(&mut self, prefix: &str, dir_path: &str, options: StaticOptions) -> io::Result<()> {
+        let options = options.or_mime_types(&self.mime_types);
+        if options.is_preload() {
+            let files = serve_static::read_tree(path::Path::new(dir_path), &options)?;
+            return self.serve_memory(prefix, &files, options);
+        }
+        let dir = sync::Arc::new(StaticDir::new(prefix, dir_path, options)?);
+        let matching = dir.clone();
+        let matcher: MatcherRef = sync::Arc::new(move |req: &mut Req| *req.method() == Method::GET && matching.matches(req.path()));
+        // `StaticDir::serve` only needs `&self`, so concurrent requests to the mount each get their own
+        // handler instead of taking turns on one lock while bodies are sent
+        let make = move || -> HandlerRef {
+            let dir = dir.clone();
+            sync::Arc::new(sync::Mutex::new(move |req: &mut Req, res: &mut Res| {
+                if let Err(e) = dir.serve(req, res) {
+                    error!("failed to serve static path {}: {}", req.path(), e);
+                }
+            }))
+        };
+        self.filters.push(MatchEntry { matcher, handler: EntryHandler::PerRequest(sync::Arc::new(make)) });
+        Ok(())
+    }
+
+    /// Serves a directory compiled into the binary at `prefix`, like a preloaded `serve_static_with`.
+    /// See `embed::generate` for how to embed one.
+    pub fn serve_embedded(&mut self, prefix: &str, dir: &EmbeddedDir, options: StaticOptions) -> io::Result<()> {
+        let options = options.or_mime_types(&self.mime_types);
+        self.serve_memory(prefix, &dir.to_tree(), options)
+    }
+
+    fn serve_memory(&mut self, prefix: &str, files: &MemoryTree, options: StaticOptions) -> io::Result<()> {
+        serve_static::add_tree_to_trie(prefix, files, &mut self.trie, &options);
+        // the trie only knows the files, so the fallback catches what it misses
+        if let Some(fallback) = options.spa_fallback() {
+            let handler = serve_static::memory_file_handler(fallback.trim_start_matches('/'), files, &options)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("SPA fallback {} not found", fallback)))?;
+            let prefix = prefix.trim_end_matches('/').to_string();
+            let matcher: MatcherRef = sync::Arc::new(move |req: &mut Req| {
+                *req.method() == Method::GET
+                    && serve_static::under_prefix(&prefix, req.path()).and_then(|relative| options.falls_back(relative)).is_some()
+            });
+            self.add_handler_from_matchers(vec![matcher], handler);
+        }
+        Ok(())
     }
 
     fn combine(matchers: Vec<MatcherRef>) -> MatcherRef{
@@ -188,4 +269,61 @@ mod matcher_test {
 
         assert_eq!(res.status(), "bad login"); 
     }
-}
\ No newline at end of file
+
+    fn serve(mux: &Muxer, target: &str) -> String {
+        let raw = format!("GET {} HTTP/1.1\r\n\r\n", target);
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf).unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        match mux.get_handler(&mut req) {
+            Some(mut handler) => handler.handle(&mut req, &mut Res::new(&mut write_buf)),
+            None => return String::from("no handler"),
+        }
+        String::from_utf8(write_buf).unwrap()
+    }
+
+    #[test]
+    fn serves_static_requests_concurrently() {
+        let mut mux = Muxer::default();
+        mux.serve_static("/", "test_data").unwrap();
+        let raw = "GET /assets/main.css HTTP/1.1\r\n\r\n";
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf).unwrap();
+        let first = mux.get_handler(&mut req).unwrap();
+        let mut second = mux.get_handler(&mut req).unwrap();
+        assert!(!sync::Arc::ptr_eq(&first, &second));
+        // a request still being served doesn't hold up the next one
+        let _serving = first.lock().unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        second.handle(&mut req, &mut Res::new(&mut write_buf));
+        assert!(String::from_utf8(write_buf).unwrap().ends_with("\r\n\r\n#main-title {\n    color: green;\n}"));
+    }
+
+    #[test]
+    fn serves_spa_fallback() {
+        let root = std::env::temp_dir().join(format!("hermitcrab-spa-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("assets")).unwrap();
+        std::fs::write(root.join("index.html"), "app shell").unwrap();
+        std::fs::write(root.join("assets/app.js"), "app").unwrap();
+        let dir = root.to_str().unwrap();
+
+        let mut from_disk = Muxer::default();
+        from_disk.serve_static_with("/app", dir, StaticOptions::new().spa("index.html").spa_exclude("/api/**")).unwrap();
+        let mut preloaded = Muxer::default();
+        preloaded.serve_static_with("/app", dir, StaticOptions::new().spa("index.html").spa_exclude("/api/**").preload(true)).unwrap();
+        for mux in [&from_disk, &preloaded].iter() {
+            for route in ["/app/", "/app/users/42", "/app/settings/", "/app/assets/", "/app/users/42?tab=1"].iter() {
+                let res = serve(mux, route);
+                assert!(res.starts_with("HTTP/1.x 200 OK\r\n"), "{}: {}", route, res);
+                assert!(res.contains("Content-Type: text/html; charset=utf-8\r\n"));
+                assert!(res.ends_with("\r\n\r\napp shell"), "{}", route);
+            }
+            assert!(serve(mux, "/app/assets/app.js").ends_with("\r\n\r\napp"));
+            for missing in ["/app/assets/missing.js", "/app/users/avatar.png", "/app/api/users", "/app/api", "/app/api/", "/app/.env/x", "/other"].iter() {
+                let res = serve(mux, missing);
+                assert!(res == "no handler" || res.starts_with("HTTP/1.x 404 Not Found\r\n"), "{}: {}", missing, res);
+            }
+        }
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    path,
+    sync::{Arc, RwLock},
+};
+
+pub const TEXT_PLAIN: &str = "text/plain; charset=utf-8";
+pub const TEXT_HTML: &str = "text/html; charset=utf-8";
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+// Textual types carry a charset so browsers do not have to guess it.
+const TYPES: &[(&str, &str)] = &[
+    ("html", TEXT_HTML),
+    ("htm", TEXT_HTML),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("webmanifest", "application/manifest+json"),
+    ("txt", TEXT_PLAIN),
+    ("md", "text/markdown; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("xml", "application/xml; charset=utf-8"),
+    ("rss", "application/rss+xml; charset=utf-8"),
+    ("atom", "application/atom+xml; charset=utf-8"),
+    ("yaml", "application/yaml; charset=utf-8"),
+    ("yml", "application/yaml; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("oga", "audio/ogg"),
+    ("m4a", "audio/mp4"),
+    ("flac", "audio/flac"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("vtt", "text/vtt; charset=utf-8"),
+];
+
+fn extension(p: &path::Path) -> Option<String> {
+    p.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase())
+}
+
+/// The content type registered for a file extension (without the dot, any case).
+pub fn from_extension(ext: &str) -> Option<&'static str> {
+    TYPES.iter().find(|(e, _)| e.eq_ignore_ascii_case(ext)).map(|(_, t)| *t)
+}
+
+/// Guesses the content type of a file from its extension, falling back to `application/octet-stream`.
+pub fn from_path(p: &path::Path) -> &'static str {
+    extension(p).and_then(|ext| from_extension(&ext)).unwrap_or(OCTET_STREAM)
+}
+
+/// Adds `charset=utf-8` to textual types that do not name a charset.
+pub fn with_default_charset(content_type: &str) -> String {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let textual = essence.starts_with("text/") || essence.ends_with("+xml") || essence == "application/xml" || essence == "application/javascript";
+    if textual && !content_type.to_ascii_lowercase().contains("charset=") {
+        format!("{}; charset=utf-8", content_type)
+    } else {
+        content_type.to_string()
+    }
+}
+
+/// Extension to content type overrides consulted before the built-in table. Clones share
+/// the same map, so types added after a directory is mounted still apply to it.
+#[derive(Clone, Debug, Default)]
+pub struct MimeTypes {
+    overrides: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl MimeTypes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves files ending in `.ext` as `content_type`, with a UTF-8 charset added to textual types.
+    pub fn insert(&self, ext: &str, content_type: &str) {
+        let ext = ext.trim_start_matches('.').to_ascii_lowercase();
+        self.overrides.write().unwrap().insert(ext, with_default_charset(content_type));
+    }
+
+    pub fn lookup(&self, p: &path::Path) -> String {
+        let ext = match extension(p) {
+            Some(ext) => ext,
+            None => return OCTET_STREAM.to_string(),
+        };
+        if let Some(t) = self.overrides.read().unwrap().get(&ext) {
+            return t.clone();
+        }
+        from_extension(&ext).unwrap_or(OCTET_STREAM).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_by_extension() {
+        assert_eq!(from_path(path::Path::new("a/index.HTML")), TEXT_HTML);
+        assert_eq!(from_path(path::Path::new("main.css")), "text/css; charset=utf-8");
+        assert_eq!(from_path(path::Path::new("font.woff2")), "font/woff2");
+        assert_eq!(from_path(path::Path::new("archive")), OCTET_STREAM);
+        assert_eq!(from_path(path::Path::new("data.bin")), OCTET_STREAM);
+    }
+
+    #[test]
+    fn applies_overrides_with_charset() {
+        let types = MimeTypes::new();
+        let shared = types.clone();
+        types.insert(".tmpl", "text/x-template");
+        types.insert("json", "application/ld+json");
+        types.insert("dat", "text/plain; charset=latin1");
+        assert_eq!(shared.lookup(path::Path::new("page.TMPL")), "text/x-template; charset=utf-8");
+        assert_eq!(shared.lookup(path::Path::new("a.json")), "application/ld+json");
+        assert_eq!(shared.lookup(path::Path::new("a.dat")), "text/plain; charset=latin1");
+        assert_eq!(shared.lookup(path::Path::new("a.css")), "text/css; charset=utf-8");
+        assert_eq!(with_default_charset("image/svg+xml"), "image/svg+xml; charset=utf-8");
+    }
+}
@@ -3,14 +3,59 @@ pub mod res;
 pub mod method;
 pub mod matcher;
 pub mod serve_static;
+pub mod cookie;
+pub mod date;
+pub mod body;
+pub mod form;
+pub mod multipart;
+pub mod json;
+pub mod limits;
+pub mod error;
+pub mod url;
+pub mod status;
+pub mod mime;
+pub mod conn;
+pub mod cidr;
+pub mod proxy_protocol;
+pub mod forwarded;
+pub mod conditional;
+pub mod cache;
+pub mod range;
+pub mod autoindex;
+pub mod encoding;
+pub mod glob;
+pub mod embed;
 mod headers;
 mod trie;
+mod parser;
+mod sendfile;
 
 pub use {
     req::Req,
     res::Res,
     method::Method,
+    status::StatusCode,
+    conn::ConnInfo,
+    cidr::{Cidr, CidrError},
+    proxy_protocol::{ProxyProtocol, ProxyHeader, ProxyError},
+    forwarded::Forwarded,
+    serve_static::{StaticDir, StaticOptions, SymlinkPolicy, EtagStrategy},
+    mime::MimeTypes,
+    conditional::{ETag, Precondition, Validators},
+    cache::{CacheControl, CacheRule},
+    range::{ByteRange, RangeRequest},
+    autoindex::Listing,
+    encoding::ContentEncoding,
+    glob::Glob,
+    embed::EmbeddedDir,
     matcher::{Muxer, MatchChain},
+    cookie::{Cookie, CookieError, SameSite},
+    body::Body,
+    form::{Form, FormError},
+    multipart::{Multipart, MultipartError, Part, SavedFile},
+    json::{JsonValue, JsonError},
+    limits::{Limits, LimitError},
+    error::ParseError,
     handler::Handle,
 };
 
@@ -22,6 +67,8 @@ pub mod handler {
     use super::*;
     pub type Handler = FnMut(&mut Req, &mut Res) + Send + Sync + 'static;
     pub type HandlerRef = sync::Arc<sync::Mutex<Handler>>;
+    pub type ErrorHandler = dyn Fn(&ParseError, &mut Res) + Send + Sync + 'static;
+    pub type ErrorHandlerRef = sync::Arc<ErrorHandler>;
 
     pub trait Handle {
         fn handle(&mut self, request: &mut Req, response: &mut Res);
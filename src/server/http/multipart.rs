@@ -0,0 +1,555 @@
+use {
+    std::{
+        error, fmt, fs, io, path, process, time,
+        io::{Read, Write},
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+    super::status::StatusCode,
+    crate::logger::help::*,
+};
+
+pub const MULTIPART_CONTENT_TYPE: &str = "multipart/form-data";
+pub const DEFAULT_PART_SIZE_LIMIT: u64 = 16 * 1024 * 1024;
+pub const DEFAULT_MULTIPART_SIZE_LIMIT: u64 = 64 * 1024 * 1024;
+
+const MAX_BOUNDARY_LENGTH: usize = 70;
+const MAX_PART_HEADER_LINE_LENGTH: usize = 4096;
+const MAX_PART_HEADER_COUNT: usize = 32;
+const READ_CHUNK_SIZE: usize = 8192;
+
+static SPOOL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Debug)]
+pub enum MultipartError {
+    UnsupportedContentType(Option<String>),
+    InvalidBoundary(String),
+    PartTooLarge(u64),
+    TooLarge(u64),
+    Malformed(String),
+    Io(io::Error),
+}
+
+impl MultipartError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            MultipartError::PartTooLarge(_) | MultipartError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultipartError::UnsupportedContentType(Some(t)) => write!(f, "MultipartError::UnsupportedContentType: {}", t),
+            MultipartError::UnsupportedContentType(None) => write!(f, "MultipartError::UnsupportedContentType: missing Content-Type"),
+            MultipartError::InvalidBoundary(b) => write!(f, "MultipartError::InvalidBoundary: \"{}\"", b),
+            MultipartError::PartTooLarge(limit) => write!(f, "MultipartError::PartTooLarge: part exceeds {} bytes", limit),
+            MultipartError::TooLarge(limit) => write!(f, "MultipartError::TooLarge: body exceeds {} bytes", limit),
+            MultipartError::Malformed(msg) => write!(f, "MultipartError::Malformed: {}", msg),
+            MultipartError::Io(e) => write!(f, "MultipartError::Io: {}", e),
+        }
+    }
+}
+
+impl error::Error for MultipartError {}
+
+impl From<io::Error> for MultipartError {
+    fn from(e: io::Error) -> Self {
+        // errors raised inside `Part::read` travel wrapped in an io::Error
+        if e.get_ref().map(|inner| inner.is::<MultipartError>()).unwrap_or(false) {
+            return *e.into_inner().unwrap().downcast::<MultipartError>().unwrap();
+        }
+        MultipartError::Io(e)
+    }
+}
+
+impl From<MultipartError> for io::Error {
+    fn from(e: MultipartError) -> Self {
+        match e {
+            MultipartError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum State {
+    Preamble,
+    Part,
+    AfterDelimiter,
+    Done,
+}
+
+/// Streaming `multipart/form-data` parser.
+///
+/// Parts are handed out one at a time by `next_part`; any unread data of the previous part
+/// is skipped when the next one is requested.
+pub struct Multipart<R: io::Read> {
+    reader: R,
+    delimiter: Vec<u8>,
+    buf: Vec<u8>,
+    pos: usize,
+    state: State,
+    part_limit: u64,
+    total_limit: u64,
+    total_read: u64,
+    part_read: u64,
+}
+
+impl<R: io::Read> Multipart<R> {
+    pub fn new(reader: R, boundary: &str) -> Result<Self, MultipartError> {
+        Self::with_limits(reader, boundary, DEFAULT_PART_SIZE_LIMIT, DEFAULT_MULTIPART_SIZE_LIMIT)
+    }
+
+    pub fn with_limits(reader: R, boundary: &str, part_limit: u64, total_limit: u64) -> Result<Self, MultipartError> {
+        validate_boundary(boundary)?;
+        let mut delimiter = b"\r\n--".to_vec();
+        delimiter.extend_from_slice(boundary.as_bytes());
+        Ok(Self {
+            reader,
+            delimiter,
+            // the leading CRLF lets the first boundary line match like any other delimiter
+            buf: b"\r\n".to_vec(),
+            pos: 0,
+            state: State::Preamble,
+            part_limit,
+            total_limit,
+            total_read: 0,
+            part_read: 0,
+        })
+    }
+
+    pub fn next_part(&mut self) -> Result<Option<Part<'_, R>>, MultipartError> {
+        let mut sink = [0u8; READ_CHUNK_SIZE];
+        while self.state == State::Preamble || self.state == State::Part {
+            self.read_data(&mut sink)?;
+        }
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        while self.buf.len() - self.pos < 2 {
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end after boundary".to_string()));
+            }
+        }
+        if &self.buf[self.pos..self.pos + 2] == b"--" {
+            self.state = State::Done;
+            return Ok(None);
+        }
+        let boundary_rest = self.read_line()?;
+        if !boundary_rest.iter().all(|b| *b == b' ' || *b == b'\t') {
+            return Err(MultipartError::Malformed("unexpected data after boundary".to_string()));
+        }
+
+        let mut headers = vec![];
+        loop {
+            let line = self.read_line()?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_PART_HEADER_COUNT {
+                return Err(MultipartError::Malformed("too many part headers".to_string()));
+            }
+            let line = String::from_utf8(line)
+                .map_err(|_| MultipartError::Malformed("part header is not valid utf8".to_string()))?;
+            let mut kv = line.splitn(2, ':');
+            let k = kv.next().unwrap_or("").trim().to_string();
+            let v = kv.next()
+                .ok_or_else(|| MultipartError::Malformed(format!("invalid part header \"{}\"", line)))?
+                .trim()
+                .to_string();
+            headers.push((k, v));
+        }
+
+        self.state = State::Part;
+        self.part_read = 0;
+        Ok(Some(Part {
+            mp: self,
+            headers: PartHeaders::new(headers),
+        }))
+    }
+
+    // Reads data up to the next delimiter. Returns 0 once the delimiter has been consumed.
+    fn read_data(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.state != State::Preamble && self.state != State::Part {
+                return Ok(0);
+            }
+            let available = &self.buf[self.pos..];
+            if let Some(i) = find(available, &self.delimiter) {
+                if i == 0 {
+                    self.pos += self.delimiter.len();
+                    self.state = State::AfterDelimiter;
+                    return Ok(0);
+                }
+                let n = i.min(out.len());
+                out[..n].copy_from_slice(&available[..n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            // keep enough bytes back to recognise a delimiter split across reads
+            let safe = available.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                let n = safe.min(out.len());
+                out[..n].copy_from_slice(&available[..n]);
+                self.pos += n;
+                return Ok(n);
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of multipart body".to_string()).into());
+            }
+        }
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, MultipartError> {
+        loop {
+            if let Some(i) = find(&self.buf[self.pos..], b"\r\n") {
+                let line = self.buf[self.pos..self.pos + i].to_vec();
+                self.pos += i + 2;
+                return Ok(line);
+            }
+            if self.buf.len() - self.pos > MAX_PART_HEADER_LINE_LENGTH {
+                return Err(MultipartError::Malformed("part header line too long".to_string()));
+            }
+            if !self.fill()? {
+                return Err(MultipartError::Malformed("unexpected end of part headers".to_string()));
+            }
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.pos > 0 {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+        let len = self.buf.len();
+        self.buf.resize(len + READ_CHUNK_SIZE, 0);
+        let n = match self.reader.read(&mut self.buf[len..]) {
+            Ok(n) => n,
+            Err(e) => {
+                self.buf.truncate(len);
+                return Err(e);
+            },
+        };
+        self.buf.truncate(len + n);
+        self.total_read += n as u64;
+        if self.total_read > self.total_limit {
+            return Err(MultipartError::TooLarge(self.total_limit).into());
+        }
+        Ok(n > 0)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PartHeaders {
+    headers: Vec<(String, String)>,
+    name: Option<String>,
+    filename: Option<String>,
+    content_type: Option<String>,
+}
+
+impl PartHeaders {
+    fn new(headers: Vec<(String, String)>) -> Self {
+        let mut ph = PartHeaders {
+            headers,
+            ..Default::default()
+        };
+        if let Some(disposition) = ph.get("Content-Disposition").map(|d| d.to_string()) {
+            ph.name = header_param(&disposition, "name");
+            ph.filename = header_param(&disposition, "filename");
+        }
+        ph.content_type = ph.get("Content-Type").map(|t| t.to_string());
+        ph
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.headers.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// One part of a multipart body. Reading it yields the part's content.
+pub struct Part<'m, R: io::Read> {
+    mp: &'m mut Multipart<R>,
+    headers: PartHeaders,
+}
+
+impl<'m, R: io::Read> Part<'m, R> {
+    pub fn name(&self) -> Option<&str> {
+        self.headers.name.as_deref()
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.headers.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.headers.content_type.as_deref()
+    }
+
+    pub fn headers(&self) -> &PartHeaders {
+        &self.headers
+    }
+
+    /// Reads the whole part as utf8 text.
+    pub fn text(&mut self) -> Result<String, MultipartError> {
+        let mut s = String::new();
+        self.read_to_string(&mut s)?;
+        Ok(s)
+    }
+
+    /// Streams the part into a new file in the system temp directory.
+    pub fn spool(&mut self) -> Result<SavedFile, MultipartError> {
+        self.save_to(&std::env::temp_dir())
+    }
+
+    /// Streams the part into a new, uniquely named file inside `dir`.
+    /// The file is removed when the returned `SavedFile` is dropped unless it is persisted.
+    pub fn save_to(&mut self, dir: &path::Path) -> Result<SavedFile, MultipartError> {
+        let (path, file) = create_spool_file(dir)?;
+        let mut saved = SavedFile {
+            path,
+            size: 0,
+            filename: self.headers.filename.clone(),
+            content_type: self.headers.content_type.clone(),
+            persisted: false,
+        };
+        let mut w = io::BufWriter::new(file);
+        saved.size = io::copy(self, &mut w)?;
+        w.flush()?;
+        Ok(saved)
+    }
+}
+
+impl<'m, R: io::Read> io::Read for Part<'m, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.mp.state != State::Part {
+            return Ok(0);
+        }
+        let n = self.mp.read_data(buf)?;
+        self.mp.part_read += n as u64;
+        if self.mp.part_read > self.mp.part_limit {
+            return Err(MultipartError::PartTooLarge(self.mp.part_limit).into());
+        }
+        Ok(n)
+    }
+}
+
+/// An uploaded part stored on disk.
+#[derive(Debug)]
+pub struct SavedFile {
+    path: path::PathBuf,
+    size: u64,
+    filename: Option<String>,
+    content_type: Option<String>,
+    persisted: bool,
+}
+
+impl SavedFile {
+    pub fn path(&self) -> &path::Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Moves the file to `dest` and keeps it after drop.
+    pub fn persist(mut self, dest: &path::Path) -> io::Result<()> {
+        if fs::rename(&self.path, dest).is_err() {
+            // rename fails across file systems
+            fs::copy(&self.path, dest)?;
+            fs::remove_file(&self.path)?;
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for SavedFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            if let Err(e) = fs::remove_file(&self.path) {
+                warn!("failed to remove spooled upload {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+fn create_spool_file(dir: &path::Path) -> io::Result<(path::PathBuf, fs::File)> {
+    loop {
+        let nanos = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+        let name = format!("hermitcrab-{}-{}-{}.upload", process::id(), nanos, SPOOL_COUNTER.fetch_add(1, Ordering::SeqCst));
+        let p = dir.join(name);
+        match fs::OpenOptions::new().write(true).create_new(true).open(&p) {
+            Ok(f) => return Ok((p, f)),
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Extracts a parameter such as `boundary` or `filename` from a header value,
+/// unquoting it if needed.
+pub fn header_param(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|param| {
+        let mut kv = param.splitn(2, '=');
+        let k = kv.next()?.trim();
+        if !k.eq_ignore_ascii_case(name) {
+            return None;
+        }
+        let v = kv.next()?.trim();
+        if v.len() >= 2 && v.starts_with('"') && v.ends_with('"') {
+            Some(v[1..v.len() - 1].replace("\\\"", "\""))
+        } else {
+            Some(v.to_string())
+        }
+    })
+}
+
+fn validate_boundary(boundary: &str) -> Result<(), MultipartError> {
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c);
+    if boundary.is_empty()
+        || boundary.len() > MAX_BOUNDARY_LENGTH
+        || boundary.ends_with(' ')
+        || !boundary.chars().all(valid_char) {
+        return Err(MultipartError::InvalidBoundary(boundary.to_string()));
+    }
+    Ok(())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BODY: &str = "preamble\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        hello\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"upload\"; filename=\"a.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        line one\r\n--Xy\r\n\
+        --XyZ--\r\n\
+        epilogue";
+
+    // hands out one byte per read to exercise delimiters split across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> io::Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() || buf.is_empty() {
+                return Ok(0);
+            }
+            buf[0] = self.0[0];
+            self.0 = &self.0[1..];
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn parses_parts() {
+        for reader in [Box::new(BODY.as_bytes()) as Box<dyn io::Read>, Box::new(Trickle(BODY.as_bytes()))] {
+            let mut mp = Multipart::new(reader, "XyZ").unwrap();
+            {
+                let mut p = mp.next_part().unwrap().unwrap();
+                assert_eq!(p.name(), Some("title"));
+                assert_eq!(p.filename(), None);
+                assert_eq!(p.text().unwrap(), "hello");
+            }
+            {
+                let mut p = mp.next_part().unwrap().unwrap();
+                assert_eq!(p.name(), Some("upload"));
+                assert_eq!(p.filename(), Some("a.txt"));
+                assert_eq!(p.content_type(), Some("text/plain"));
+                assert_eq!(p.text().unwrap(), "line one\r\n--Xy");
+            }
+            assert!(mp.next_part().unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn skips_unread_part_data() {
+        let mut mp = Multipart::new(BODY.as_bytes(), "XyZ").unwrap();
+        mp.next_part().unwrap().unwrap();
+        let p = mp.next_part().unwrap().unwrap();
+        assert_eq!(p.name(), Some("upload"));
+    }
+
+    #[test]
+    fn enforces_limits() {
+        let mut mp = Multipart::with_limits(BODY.as_bytes(), "XyZ", 4, 1024).unwrap();
+        match mp.next_part().unwrap().unwrap().text() {
+            Err(MultipartError::PartTooLarge(4)) => {},
+            other => panic!("unexpected: {:?}", other),
+        }
+
+        let mut mp = Multipart::with_limits(BODY.as_bytes(), "XyZ", 1024, 16).unwrap();
+        match mp.next_part() {
+            Err(MultipartError::TooLarge(16)) => {},
+            Err(e) => panic!("unexpected: {:?}", e),
+            Ok(_) => panic!("limit not enforced"),
+        }
+    }
+
+    #[test]
+    fn validates_boundary() {
+        assert!(Multipart::new(BODY.as_bytes(), "").is_err());
+        assert!(Multipart::new(BODY.as_bytes(), "ends with space ").is_err());
+        assert!(Multipart::new(BODY.as_bytes(), "bad\"quote").is_err());
+        assert!(Multipart::new(BODY.as_bytes(), &"x".repeat(71)).is_err());
+        assert!(Multipart::new(BODY.as_bytes(), "----WebKitFormBoundary7MA4YWxkTrZu0gW").is_ok());
+    }
+
+    #[test]
+    fn rejects_truncated_body() {
+        let mut mp = Multipart::new("--XyZ\r\n\r\nabc".as_bytes(), "XyZ").unwrap();
+        let mut p = mp.next_part().unwrap().unwrap();
+        match p.text() {
+            Err(MultipartError::Malformed(_)) => {},
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spools_to_directory() {
+        let mut mp = Multipart::new(BODY.as_bytes(), "XyZ").unwrap();
+        mp.next_part().unwrap().unwrap();
+        let saved = mp.next_part().unwrap().unwrap().spool().unwrap();
+        let spooled_path = saved.path().to_owned();
+        assert_eq!(saved.size(), 14);
+        assert_eq!(saved.filename(), Some("a.txt"));
+        assert_eq!(fs::read(&spooled_path).unwrap(), b"line one\r\n--Xy");
+        drop(saved);
+        assert!(!spooled_path.exists());
+    }
+
+    #[test]
+    fn parses_header_params() {
+        let v = "multipart/form-data; boundary=\"a b\"; charset=utf-8";
+        assert_eq!(header_param(v, "boundary"), Some("a b".to_string()));
+        assert_eq!(header_param(v, "Charset"), Some("utf-8".to_string()));
+        assert_eq!(header_param(v, "missing"), None);
+    }
+}
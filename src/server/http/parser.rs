@@ -0,0 +1,285 @@
+use {
+    std::io,
+    super::{
+        limits::{Limits, LimitError},
+        error::ParseError,
+    },
+};
+
+/// Byte offsets `(start, end)` into the request head.
+pub(crate) type Span = (usize, usize);
+
+/// Positions of the parts of a request head inside its buffer.
+pub(crate) struct Head {
+    pub method: Span,
+    pub target: Span,
+    pub version: Span,
+    pub headers: Vec<(Span, Span)>,
+}
+
+/// Copies the request head (request line, header fields and the empty line) from `s` into `buf`.
+/// Only the head is consumed from `s`, so the body can be read from it afterwards.
+pub(crate) fn read_head(s: &mut dyn io::BufRead, buf: &mut Vec<u8>, limits: &Limits) -> Result<(), ParseError> {
+    let mut skipped = 0usize;
+    let mut request_line_end: Option<usize> = None;
+    loop {
+        let available = s.fill_buf()?;
+        if available.is_empty() {
+            if buf.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before a full request was read").into());
+            }
+            return Err(ParseError::MissingCrlf);
+        }
+
+        // RFC 9112 2.2: empty lines received before the request line are ignored
+        if buf.is_empty() {
+            let blank = available.iter().take_while(|b| **b == b'\r' || **b == b'\n').count();
+            if blank > 0 {
+                skipped += blank;
+                s.consume(blank);
+                if skipped > limits.max_request_line {
+                    return Err(LimitError::RequestLineTooLong.into());
+                }
+                continue;
+            }
+        }
+
+        let old_len = buf.len();
+        let chunk_len = available.len();
+        buf.extend_from_slice(available);
+        let end = find_head_end(buf, old_len.saturating_sub(2));
+        let used = match end {
+            Some(end) => end - old_len,
+            None => chunk_len,
+        };
+        buf.truncate(old_len + used);
+        s.consume(used);
+
+        if request_line_end.is_none() {
+            request_line_end = memchr(b'\n', &buf[old_len..]).map(|i| old_len + i + 1);
+        }
+        match request_line_end {
+            Some(line_end) => {
+                if line_end - 1 > limits.max_request_line + 1 {
+                    return Err(LimitError::RequestLineTooLong.into());
+                }
+                // the header section includes the CRLF of every field plus the closing empty line
+                if buf.len() - line_end > limits.max_header_bytes + 2 {
+                    return Err(LimitError::HeadersTooLarge.into());
+                }
+            },
+            None if buf.len() > limits.max_request_line + 2 => {
+                return Err(LimitError::RequestLineTooLong.into());
+            },
+            None => {},
+        }
+        if end.is_some() {
+            return Ok(());
+        }
+    }
+}
+
+// Returns the offset right after the empty line that ends the head. Bare LF is accepted as a line ending.
+fn find_head_end(buf: &[u8], from: usize) -> Option<usize> {
+    let mut i = from;
+    while let Some(p) = memchr(b'\n', &buf[i..]) {
+        let lf = i + p;
+        match (buf.get(lf + 1), buf.get(lf + 2)) {
+            (Some(b'\n'), _) => return Some(lf + 2),
+            (Some(b'\r'), Some(b'\n')) => return Some(lf + 3),
+            _ => i = lf + 1,
+        }
+    }
+    None
+}
+
+/// Splits a head read by `read_head` into spans without allocating per field.
+pub(crate) fn parse_head(head: &str, limits: &Limits) -> Result<Head, ParseError> {
+    let bytes = head.as_bytes();
+    let mut lines = LineIter { bytes, pos: 0 };
+
+    let (line_start, line_end) = lines.next().ok_or(ParseError::MissingCrlf)?;
+    let (method, target, version) = parse_request_line(head, line_start, line_end)?;
+
+    let mut headers = Vec::with_capacity(16);
+    for (start, end) in lines {
+        if start == end {
+            break;
+        }
+        if headers.len() == limits.max_header_count {
+            return Err(LimitError::TooManyHeaders.into());
+        }
+        headers.push(parse_header_line(head, start, end)?);
+    }
+    Ok(Head {
+        method,
+        target,
+        version,
+        headers,
+    })
+}
+
+fn parse_request_line(head: &str, start: usize, end: usize) -> Result<(Span, Span, Span), ParseError> {
+    let line = &head.as_bytes()[start..end];
+    let invalid = || ParseError::InvalidRequestLine(head[start..end].to_string());
+
+    let sp1 = memchr(b' ', line).ok_or_else(invalid)?;
+    let sp2 = memchr(b' ', &line[sp1 + 1..]).map(|i| sp1 + 1 + i).ok_or_else(invalid)?;
+    let method = &line[..sp1];
+    let target = &line[sp1 + 1..sp2];
+    let version = &line[sp2 + 1..];
+
+    if method.is_empty() || !method.iter().all(|b| is_tchar(*b)) {
+        return Err(invalid());
+    }
+    if target.is_empty() || !target.iter().all(|b| b.is_ascii_graphic() || *b >= 0x80) {
+        return Err(invalid());
+    }
+    match version {
+        [b'H', b'T', b'T', b'P', b'/', major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+            if *major != b'1' {
+                return Err(ParseError::UnsupportedVersion(head[start + sp2 + 1..end].to_string()));
+            }
+        },
+        _ => return Err(invalid()),
+    }
+    Ok((
+        (start, start + sp1),
+        (start + sp1 + 1, start + sp2),
+        (start + sp2 + 1, end),
+    ))
+}
+
+fn parse_header_line(head: &str, start: usize, end: usize) -> Result<(Span, Span), ParseError> {
+    let line = &head.as_bytes()[start..end];
+    let invalid = || ParseError::InvalidHeader(head[start..end].to_string());
+
+    // no whitespace is allowed between the name and the colon, which also rules out obs-fold
+    let colon = memchr(b':', line).ok_or_else(invalid)?;
+    if colon == 0 || !line[..colon].iter().all(|b| is_tchar(*b)) {
+        return Err(invalid());
+    }
+    let is_ows = |b: &u8| *b == b' ' || *b == b'\t';
+    let value = &line[colon + 1..];
+    let leading = value.iter().take_while(|b| is_ows(b)).count();
+    let trailing = value[leading..].iter().rev().take_while(|b| is_ows(b)).count();
+    let value_start = colon + 1 + leading;
+    let value_end = line.len() - trailing;
+    if !line[value_start..value_end].iter().all(|b| *b == b'\t' || (*b >= 0x20 && *b != 0x7f)) {
+        return Err(invalid());
+    }
+    Ok((
+        (start, start + colon),
+        (start + value_start, start + value_end),
+    ))
+}
+
+// RFC 9110 5.6.2, as a lookup table since every byte of every field name goes through it
+static TCHAR: [bool; 256] = {
+    let mut table = [false; 256];
+    let mut i = 0;
+    while i < 256 {
+        let b = i as u8;
+        table[i] = b.is_ascii_alphanumeric() || matches!(b, b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~');
+        i += 1;
+    }
+    table
+};
+
+fn is_tchar(b: u8) -> bool {
+    TCHAR[b as usize]
+}
+
+fn memchr(needle: u8, haystack: &[u8]) -> Option<usize> {
+    haystack.iter().position(|b| *b == needle)
+}
+
+// Yields `(start, end)` of each line with its CRLF or LF stripped.
+struct LineIter<'b> {
+    bytes: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> Iterator for LineIter<'b> {
+    type Item = Span;
+
+    fn next(&mut self) -> Option<Span> {
+        let start = self.pos;
+        let lf = start + memchr(b'\n', &self.bytes[start..])?;
+        self.pos = lf + 1;
+        let end = if lf > start && self.bytes[lf - 1] == b'\r' { lf - 1 } else { lf };
+        Some((start, end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(raw: &str, limits: &Limits) -> Result<(String, String), ParseError> {
+        let mut src = io::BufReader::with_capacity(7, raw.as_bytes());
+        let mut buf = vec![];
+        read_head(&mut src, &mut buf, limits)?;
+        let mut rest = String::new();
+        io::Read::read_to_string(&mut src, &mut rest)?;
+        Ok((String::from_utf8(buf).unwrap(), rest))
+    }
+
+    #[test]
+    fn reads_only_the_head() {
+        let (head, rest) = read("\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\nbody", &Limits::default()).unwrap();
+        assert_eq!(head, "GET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(rest, "body");
+
+        let (head, rest) = read("GET / HTTP/1.1\nHost: a\n\nbody", &Limits::default()).unwrap();
+        assert_eq!(head, "GET / HTTP/1.1\nHost: a\n\n");
+        assert_eq!(rest, "body");
+    }
+
+    #[test]
+    fn enforces_limits_while_reading() {
+        let limits = Limits {
+            max_request_line: 16,
+            max_header_bytes: 12,
+            ..Limits::default()
+        };
+        assert!(read("GET / HTTP/1.1\r\nA: 1234567\r\n\r\n", &limits).is_ok());
+        match read("GET /0123456789 HTTP/1.1\r\n\r\n", &limits) {
+            Err(ParseError::TooLarge(LimitError::RequestLineTooLong)) => {},
+            other => panic!("unexpected: {:?}", other.map(|_| ())),
+        }
+        match read("GET / HTTP/1.1\r\nA: 12345678\r\n\r\n", &limits) {
+            Err(ParseError::TooLarge(LimitError::HeadersTooLarge)) => {},
+            other => panic!("unexpected: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn parses_spans() {
+        let head = "POST /a?b=c HTTP/1.1\r\nHost:  x.com \r\nX-Empty:\r\nAccept: */*\n\r\n";
+        let h = parse_head(head, &Limits::default()).unwrap();
+        let s = |span: Span| &head[span.0..span.1];
+        assert_eq!(s(h.method), "POST");
+        assert_eq!(s(h.target), "/a?b=c");
+        assert_eq!(s(h.version), "HTTP/1.1");
+        let headers: Vec<(&str, &str)> = h.headers.iter().map(|(k, v)| (s(*k), s(*v))).collect();
+        assert_eq!(headers, vec![("Host", "x.com"), ("X-Empty", ""), ("Accept", "*/*")]);
+    }
+
+    #[test]
+    fn rejects_invalid_tokens() {
+        let cases = vec![
+            "GET  / HTTP/1.1\r\n\r\n",
+            "G(ET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost : a\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\n folded\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\rb\r\n\r\n",
+            "GET / HTTP/1.1\r\nB@d: a\r\n\r\n",
+            "GET / HTTP/x.1\r\n\r\n",
+            "GET / HTTP/2.0\r\n\r\n",
+        ];
+        for raw in cases {
+            assert!(parse_head(raw, &Limits::default()).is_err(), "{:?} was accepted", raw);
+        }
+    }
+}
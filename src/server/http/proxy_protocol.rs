@@ -0,0 +1,229 @@
+use {
+    std::{error, fmt, io, net},
+    super::cidr::{self, Cidr},
+};
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+// a v1 header including its CRLF is never longer than this
+const V1_MAX_LENGTH: u64 = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Opt-in parsing of the HAProxy PROXY protocol (v1 and v2) at the start of a connection.
+/// Only peers inside `trusted` are expected to send the header; anyone else is served as is,
+/// so a PROXY header from them is just a malformed request.
+#[derive(Clone, Debug, Default)]
+pub struct ProxyProtocol {
+    trusted: Vec<Cidr>,
+}
+
+impl ProxyProtocol {
+    pub fn new(trusted: Vec<Cidr>) -> Self {
+        Self { trusted }
+    }
+
+    pub fn is_trusted(&self, peer: net::IpAddr) -> bool {
+        cidr::any_contains(&self.trusted, peer)
+    }
+}
+
+/// Addresses carried by a PROXY header. Both are `None` for `UNKNOWN`/`LOCAL` headers
+/// and for address families other than TCP over IPv4/IPv6.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ProxyHeader {
+    pub source: Option<net::SocketAddr>,
+    pub destination: Option<net::SocketAddr>,
+}
+
+#[derive(Debug)]
+pub enum ProxyError {
+    Missing,
+    Invalid(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProxyError::Missing => write!(f, "ProxyError::Missing"),
+            ProxyError::Invalid(reason) => write!(f, "ProxyError::Invalid: {}", reason),
+            ProxyError::Io(e) => write!(f, "ProxyError::Io: {}", e),
+        }
+    }
+}
+
+impl error::Error for ProxyError {}
+
+impl From<io::Error> for ProxyError {
+    fn from(e: io::Error) -> Self {
+        ProxyError::Io(e)
+    }
+}
+
+/// Reads a v1 or v2 PROXY header from the start of `r`, consuming exactly the header.
+pub fn read_header(r: &mut dyn io::BufRead) -> Result<ProxyHeader, ProxyError> {
+    let first = match r.fill_buf()?.first() {
+        Some(b) => *b,
+        None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed before PROXY header").into()),
+    };
+    match first {
+        b'P' => read_v1(r),
+        b'\r' => read_v2(r),
+        _ => Err(ProxyError::Missing),
+    }
+}
+
+fn read_v1(r: &mut dyn io::BufRead) -> Result<ProxyHeader, ProxyError> {
+    let mut line = vec![];
+    io::BufRead::read_until(&mut io::Read::take(&mut *r, V1_MAX_LENGTH), b'\n', &mut line)?;
+    if !line.starts_with(V1_PREFIX) {
+        return Err(ProxyError::Missing);
+    }
+    if !line.ends_with(b"\r\n") {
+        return Err(ProxyError::Invalid(String::from("v1 header not terminated by CRLF within 107 bytes")));
+    }
+    let line = std::str::from_utf8(&line[V1_PREFIX.len()..line.len() - 2])
+        .map_err(|_| ProxyError::Invalid(String::from("v1 header is not ASCII")))?;
+    let invalid = || ProxyError::Invalid(format!("malformed v1 header {:?}", line));
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["UNKNOWN", ..] => Ok(ProxyHeader::default()),
+        [proto, src, dst, sport, dport] => {
+            let (src, dst): (net::IpAddr, net::IpAddr) = match *proto {
+                "TCP4" => (src.parse::<net::Ipv4Addr>().map_err(|_| invalid())?.into(), dst.parse::<net::Ipv4Addr>().map_err(|_| invalid())?.into()),
+                "TCP6" => (src.parse::<net::Ipv6Addr>().map_err(|_| invalid())?.into(), dst.parse::<net::Ipv6Addr>().map_err(|_| invalid())?.into()),
+                _ => return Err(invalid()),
+            };
+            let port = |p: &str| if p.starts_with('0') && p != "0" { None } else { p.parse::<u16>().ok() };
+            Ok(ProxyHeader {
+                source: Some(net::SocketAddr::new(src, port(sport).ok_or_else(invalid)?)),
+                destination: Some(net::SocketAddr::new(dst, port(dport).ok_or_else(invalid)?)),
+            })
+        },
+        _ => Err(invalid()),
+    }
+}
+
+fn read_v2(r: &mut dyn io::BufRead) -> Result<ProxyHeader, ProxyError> {
+    let mut fixed = [0u8; 16];
+    io::Read::read_exact(r, &mut fixed)?;
+    if &fixed[..12] != V2_SIGNATURE {
+        return Err(ProxyError::Missing);
+    }
+    let version = fixed[12] >> 4;
+    let command = fixed[12] & 0x0f;
+    if version != 2 || command > 1 {
+        return Err(ProxyError::Invalid(format!("unsupported v2 version/command byte {:#04x}", fixed[12])));
+    }
+    let len = u16::from_be_bytes([fixed[14], fixed[15]]) as usize;
+    let mut payload = vec![0u8; len];
+    io::Read::read_exact(r, &mut payload)?;
+
+    // LOCAL connections come from the proxy itself, e.g. health checks
+    if command == 0 {
+        return Ok(ProxyHeader::default());
+    }
+    let too_short = || ProxyError::Invalid(String::from("v2 address block too short"));
+    let port = |b: &[u8]| u16::from_be_bytes([b[0], b[1]]);
+    match fixed[13] {
+        // TCP over IPv4
+        0x11 => {
+            let b = payload.get(..12).ok_or_else(too_short)?;
+            let src = net::Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+            let dst = net::Ipv4Addr::new(b[4], b[5], b[6], b[7]);
+            Ok(ProxyHeader {
+                source: Some(net::SocketAddr::new(src.into(), port(&b[8..10]))),
+                destination: Some(net::SocketAddr::new(dst.into(), port(&b[10..12]))),
+            })
+        },
+        // TCP over IPv6
+        0x21 => {
+            let b = payload.get(..36).ok_or_else(too_short)?;
+            let mut src = [0u8; 16];
+            let mut dst = [0u8; 16];
+            src.copy_from_slice(&b[..16]);
+            dst.copy_from_slice(&b[16..32]);
+            Ok(ProxyHeader {
+                source: Some(net::SocketAddr::new(net::Ipv6Addr::from(src).into(), port(&b[32..34]))),
+                destination: Some(net::SocketAddr::new(net::Ipv6Addr::from(dst).into(), port(&b[34..36]))),
+            })
+        },
+        _ => Ok(ProxyHeader::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::io::Read,
+    };
+
+    fn read(raw: &[u8]) -> (Result<ProxyHeader, ProxyError>, Vec<u8>) {
+        let mut r = io::BufReader::with_capacity(8, raw);
+        let header = read_header(&mut r);
+        let mut rest = vec![];
+        r.read_to_end(&mut rest).unwrap();
+        (header, rest)
+    }
+
+    fn addr(s: &str) -> Option<net::SocketAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    #[test]
+    fn reads_v1() {
+        let (header, rest) = read(b"PROXY TCP4 192.0.2.1 198.51.100.2 56324 443\r\nGET / HTTP/1.1\r\n");
+        let header = header.unwrap();
+        assert_eq!(header.source, addr("192.0.2.1:56324"));
+        assert_eq!(header.destination, addr("198.51.100.2:443"));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n");
+
+        let (header, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 1 2\r\n");
+        assert_eq!(header.unwrap().source, addr("[2001:db8::1]:1"));
+        let (header, _) = read(b"PROXY UNKNOWN\r\n");
+        assert_eq!(header.unwrap(), ProxyHeader::default());
+    }
+
+    #[test]
+    fn reads_v2() {
+        let mut raw = V2_SIGNATURE.to_vec();
+        raw.extend_from_slice(&[0x21, 0x11, 0x00, 0x0f]);
+        raw.extend_from_slice(&[192, 0, 2, 1, 198, 51, 100, 2, 0xdc, 0x04, 0x01, 0xbb]);
+        // a TLV the parser skips
+        raw.extend_from_slice(&[0x04, 0x00, 0x00]);
+        raw.extend_from_slice(b"GET");
+        let (header, rest) = read(&raw);
+        let header = header.unwrap();
+        assert_eq!(header.source, addr("192.0.2.1:56324"));
+        assert_eq!(header.destination, addr("198.51.100.2:443"));
+        assert_eq!(rest, b"GET");
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(read(&local).0.unwrap(), ProxyHeader::default());
+    }
+
+    #[test]
+    fn rejects_missing_or_malformed() {
+        let cases: Vec<&[u8]> = vec![
+            b"GET / HTTP/1.1\r\n\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 56324\r\n",
+            b"PROXY TCP4 2001:db8::1 198.51.100.2 1 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 01 2\r\n",
+            b"PROXY TCP4 192.0.2.1 198.51.100.2 1 2\n",
+            b"\r\n\r\n\0\r\nQUIT\n\x31\x11\x00\x00",
+            b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x04\x01\x02\x03\x04",
+            b"",
+        ];
+        for raw in cases {
+            assert!(read(raw).0.is_err(), "{:?} was accepted", String::from_utf8_lossy(raw));
+        }
+    }
+
+    #[test]
+    fn trusts_only_allowlist() {
+        let pp = ProxyProtocol::new(vec!["10.0.0.0/8".parse().unwrap()]);
+        assert!(pp.is_trusted("10.0.0.5".parse().unwrap()));
+        assert!(!pp.is_trusted("192.0.2.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,319 @@
+use {
+    std::{fs, io, sync::atomic, time},
+    super::{
+        conditional::{self, ETag, Validators},
+        date,
+        headers::*,
+        method::Method,
+        req::Req,
+        res::Res,
+        status::StatusCode,
+    },
+};
+
+// more ranges than this in one request are answered with the whole representation
+const MAX_RANGES: usize = 32;
+
+/// An inclusive range of byte positions within a representation.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The number of bytes in the range, never zero.
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// The `Content-Range` value for this range of a `total` bytes long representation.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, total)
+    }
+}
+
+/// How to answer a request, given its `Range` and `If-Range` headers.
+#[derive(Clone, PartialEq, Debug)]
+pub enum RangeRequest {
+    /// No usable `Range`, the whole representation is sent.
+    Full,
+    /// Sorted, non-overlapping ranges to send with `206 Partial Content`.
+    Partial(Vec<ByteRange>),
+    /// None of the ranges overlap the representation, answered with `416`.
+    Unsatisfiable,
+}
+
+/// Evaluates the `Range` header of a `GET` for a `len` bytes long representation. A `Range` is ignored
+/// if it is malformed, uses a unit other than bytes, or `If-Range` does not match `validators`.
+pub fn evaluate(req: &Req, validators: &Validators, len: u64) -> RangeRequest {
+    if *req.method() != Method::GET {
+        return RangeRequest::Full;
+    }
+    let range = match req.header(HTTP_HEADER_RANGE) {
+        Some(range) => range,
+        None => return RangeRequest::Full,
+    };
+    if let Some(if_range) = req.header(HTTP_HEADER_IF_RANGE) {
+        if !if_range_matches(if_range.trim(), validators) {
+            return RangeRequest::Full;
+        }
+    }
+    parse_range(range, len)
+}
+
+// A range is only sent for the representation the client already has part of,
+// which needs a strong validator: a strong ETag or the exact modification time.
+fn if_range_matches(if_range: &str, validators: &Validators) -> bool {
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        return match (ETag::parse(if_range), &validators.etag) {
+            (Some(candidate), Some(etag)) => candidate.strong_eq(etag),
+            _ => false,
+        };
+    }
+    match (date::parse_http_date(if_range), validators.last_modified) {
+        (Some(since), Some(modified)) => since == conditional::truncate(modified),
+        _ => false,
+    }
+}
+
+/// Parses a `Range` header value such as `bytes=0-499, -500` against a `len` bytes long representation.
+pub fn parse_range(header: &str, len: u64) -> RangeRequest {
+    let header = header.trim();
+    let set = match header.get(..6) {
+        Some(unit) if unit.eq_ignore_ascii_case("bytes=") => &header[6..],
+        _ => return RangeRequest::Full,
+    };
+    let mut ranges: Vec<ByteRange> = vec![];
+    let mut count = 0;
+    for spec in set.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return RangeRequest::Full;
+        }
+        let (first, last) = match spec.find('-') {
+            Some(i) => (spec[..i].trim(), spec[i + 1..].trim()),
+            None => return RangeRequest::Full,
+        };
+        let range = match (parse_pos(first), parse_pos(last)) {
+            // suffix range, the last `n` bytes
+            (None, Some(n)) if first.is_empty() => {
+                if n == 0 || len == 0 {
+                    continue;
+                }
+                ByteRange { start: len - n.min(len), end: len - 1 }
+            },
+            (Some(start), None) if last.is_empty() => {
+                if start >= len {
+                    continue;
+                }
+                ByteRange { start, end: len - 1 }
+            },
+            (Some(start), Some(end)) if start <= end => {
+                if start >= len {
+                    continue;
+                }
+                ByteRange { start, end: end.min(len - 1) }
+            },
+            _ => return RangeRequest::Full,
+        };
+        ranges.push(range);
+    }
+    if count == 0 {
+        return RangeRequest::Full;
+    }
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(coalesce(ranges))
+}
+
+fn parse_pos(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+// Merges overlapping and adjacent ranges so no byte is sent twice.
+fn coalesce(mut ranges: Vec<ByteRange>) -> Vec<ByteRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end.saturating_add(1) => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// A piece of a response body, either in memory or a slice of a file.
+pub(crate) enum Segment<'s> {
+    Bytes(Vec<u8>),
+    File { file: &'s fs::File, offset: u64, len: u64 },
+}
+
+impl Segment<'_> {
+    pub(crate) fn len(&self) -> u64 {
+        match self {
+            Segment::Bytes(b) => b.len() as u64,
+            Segment::File { len, .. } => *len,
+        }
+    }
+}
+
+/// A boundary for `multipart/byteranges` that is unlikely to occur in any content.
+pub(crate) fn new_boundary() -> String {
+    static COUNTER: atomic::AtomicU64 = atomic::AtomicU64::new(0);
+    let nanos = time::SystemTime::now().duration_since(time::UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or(0);
+    let count = COUNTER.fetch_add(1, atomic::Ordering::Relaxed);
+    let hash = conditional::fnv1a_extend(conditional::fnv1a(&nanos.to_le_bytes()), &count.to_le_bytes());
+    format!("hermitcrab-{:016x}", hash)
+}
+
+// The delimiter and headers in front of the `i`th part of a `multipart/byteranges` body.
+fn part_head(i: usize, range: &ByteRange, content_type: &str, total: u64, boundary: &str) -> String {
+    format!(
+        "{}--{}\r\n{}: {}\r\n{}: {}\r\n\r\n",
+        if i == 0 { "" } else { "\r\n" },
+        boundary,
+        HTTP_HEADER_CONTENT_TYPE,
+        content_type,
+        HTTP_HEADER_CONTENT_RANGE,
+        range.content_range(total),
+    )
+}
+
+/// The `multipart/byteranges` body for `ranges` of `file`: each range as a part with its own
+/// `Content-Type` and `Content-Range`.
+pub(crate) fn multipart_segments<'s>(file: &'s fs::File, ranges: &[ByteRange], content_type: &str, total: u64, boundary: &str) -> Vec<Segment<'s>> {
+    let mut segments = Vec::with_capacity(ranges.len() * 2 + 1);
+    for (i, range) in ranges.iter().enumerate() {
+        segments.push(Segment::Bytes(part_head(i, range, content_type, total, boundary).into_bytes()));
+        segments.push(Segment::File { file, offset: range.start, len: range.length() });
+    }
+    segments.push(Segment::Bytes(format!("\r\n--{}--\r\n", boundary).into_bytes()));
+    segments
+}
+
+/// Like `multipart_segments`, for a representation already in memory.
+pub(crate) fn multipart_body(data: &[u8], ranges: &[ByteRange], content_type: &str, boundary: &str) -> Vec<u8> {
+    let mut body = vec![];
+    for (i, range) in ranges.iter().enumerate() {
+        body.extend_from_slice(part_head(i, range, content_type, data.len() as u64, boundary).as_bytes());
+        body.extend_from_slice(&data[range.start as usize..=range.end as usize]);
+    }
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    body
+}
+
+/// Responds with `file`, or the parts of it the request's `Range` asks for, advertising `Accept-Ranges`.
+pub(crate) fn respond_file(req: &Req, res: &mut Res, validators: &Validators, file: &fs::File, len: u64, content_type: &str) -> io::Result<()> {
+    res.set_header(HTTP_HEADER_ACCEPT_RANGES, "bytes");
+    match evaluate(req, validators, len) {
+        RangeRequest::Full => res.respond_file(file, len),
+        RangeRequest::Unsatisfiable => unsatisfiable(res, len),
+        RangeRequest::Partial(ranges) => {
+            res.set_status_code(StatusCode::PARTIAL_CONTENT);
+            if let [range] = ranges.as_slice() {
+                res.set_header(HTTP_HEADER_CONTENT_RANGE, &range.content_range(len));
+                return res.respond_file_range(file, range.start, range.length());
+            }
+            let boundary = new_boundary();
+            res.set_header(HTTP_HEADER_CONTENT_TYPE, &multipart_content_type(&boundary));
+            res.respond_segments(&multipart_segments(file, &ranges, content_type, len, &boundary))
+        },
+    }
+}
+
+/// Like `respond_file`, for a representation already in memory.
+pub(crate) fn respond_bytes(req: &Req, res: &mut Res, validators: &Validators, data: &[u8], content_type: &str) -> io::Result<()> {
+    res.set_header(HTTP_HEADER_ACCEPT_RANGES, "bytes");
+    let len = data.len() as u64;
+    match evaluate(req, validators, len) {
+        RangeRequest::Full => res.respond(data),
+        RangeRequest::Unsatisfiable => unsatisfiable(res, len),
+        RangeRequest::Partial(ranges) => {
+            res.set_status_code(StatusCode::PARTIAL_CONTENT);
+            if let [range] = ranges.as_slice() {
+                res.set_header(HTTP_HEADER_CONTENT_RANGE, &range.content_range(len));
+                return res.respond(&data[range.start as usize..=range.end as usize]);
+            }
+            let boundary = new_boundary();
+            res.set_header(HTTP_HEADER_CONTENT_TYPE, &multipart_content_type(&boundary));
+            res.respond(&multipart_body(data, &ranges, content_type, &boundary))
+        },
+    }
+}
+
+fn multipart_content_type(boundary: &str) -> String {
+    format!("multipart/byteranges; boundary={}", boundary)
+}
+
+fn unsatisfiable(res: &mut Res, len: u64) -> io::Result<()> {
+    res.set_header(HTTP_HEADER_CONTENT_RANGE, &format!("bytes */{}", len));
+    res.empty(StatusCode::RANGE_NOT_SATISFIABLE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(start: u64, end: u64) -> ByteRange {
+        ByteRange { start, end }
+    }
+
+    #[test]
+    fn parses_range_sets() {
+        assert_eq!(parse_range("bytes=0-499", 1000), RangeRequest::Partial(vec![r(0, 499)]));
+        assert_eq!(parse_range("bytes=500-", 1000), RangeRequest::Partial(vec![r(500, 999)]));
+        assert_eq!(parse_range("bytes=-200", 1000), RangeRequest::Partial(vec![r(800, 999)]));
+        assert_eq!(parse_range("bytes=-2000", 1000), RangeRequest::Partial(vec![r(0, 999)]));
+        assert_eq!(parse_range("bytes=900-5000", 1000), RangeRequest::Partial(vec![r(900, 999)]));
+        assert_eq!(parse_range("Bytes= 0-0 , -1", 1000), RangeRequest::Partial(vec![r(0, 0), r(999, 999)]));
+        assert_eq!(parse_range("bytes=500-600,0-99,550-700,101-200", 1000), RangeRequest::Partial(vec![r(0, 99), r(101, 200), r(500, 700)]));
+        assert_eq!(parse_range("bytes=0-99,100-199", 1000), RangeRequest::Partial(vec![r(0, 199)]));
+        assert_eq!(parse_range("bytes=1000-", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=1000-1100,5-9", 1000), RangeRequest::Partial(vec![r(5, 9)]));
+        for ignored in ["items=0-1", "bytes=", "bytes=5-1", "bytes=a-b", "bytes=0-1-2", "bytes=+1-2", "bytes=1"].iter() {
+            assert_eq!(parse_range(ignored, 1000), RangeRequest::Full, "{}", ignored);
+        }
+        let many = format!("bytes={}", (0..40).map(|i| format!("{}-{}", i * 10, i * 10)).collect::<Vec<_>>().join(","));
+        assert_eq!(parse_range(&many, 1000), RangeRequest::Full);
+    }
+
+    fn evaluate_with(validators: &Validators, headers: &str) -> RangeRequest {
+        let raw = format!("GET / HTTP/1.1\r\nRange: bytes=0-9\r\n{}\r\n", headers);
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let req = Req::new(&mut buf).unwrap();
+        evaluate(&req, validators, 100)
+    }
+
+    #[test]
+    fn honors_if_range() {
+        let modified = time::UNIX_EPOCH + time::Duration::from_millis(784_111_777_500);
+        let strong = Validators::new(Some(ETag::strong("abc")), Some(modified));
+        let partial = RangeRequest::Partial(vec![r(0, 9)]);
+        assert_eq!(evaluate_with(&strong, ""), partial);
+        assert_eq!(evaluate_with(&strong, "If-Range: \"abc\"\r\n"), partial);
+        assert_eq!(evaluate_with(&strong, "If-Range: \"xyz\"\r\n"), RangeRequest::Full);
+        assert_eq!(evaluate_with(&strong, "If-Range: W/\"abc\"\r\n"), RangeRequest::Full);
+        assert_eq!(evaluate_with(&strong, "If-Range: Sun, 06 Nov 1994 08:49:37 GMT\r\n"), partial);
+        assert_eq!(evaluate_with(&strong, "If-Range: Sun, 06 Nov 1994 08:49:38 GMT\r\n"), RangeRequest::Full);
+        let weak = Validators::new(Some(ETag::weak("abc")), None);
+        assert_eq!(evaluate_with(&weak, "If-Range: \"abc\"\r\n"), RangeRequest::Full);
+    }
+
+    #[test]
+    fn builds_multipart_body() {
+        let body = multipart_body(b"0123456789", &[r(1, 2), r(7, 9)], "text/plain", "B");
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 1-2/10\r\n\r\n12\r\n--B\r\nContent-Type: text/plain\r\nContent-Range: bytes 7-9/10\r\n\r\n789\r\n--B--\r\n",
+        );
+        assert_ne!(new_boundary(), new_boundary());
+    }
+}
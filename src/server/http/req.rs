@@ -1,105 +1,337 @@
-
 use std::{
-    io,
-    collections,
+    io, net,
+    borrow::Cow,
 };
 
-use super::method;
+use super::{
+    method, cookie, headers::*, body::Body, form, multipart, json,
+    limits::{Limits, LimitError},
+    error::ParseError,
+    parser::{self, Span},
+    conn::ConnInfo,
+    cidr::Cidr,
+    forwarded::{self, Forwarded},
+};
 use crate::logger::help::*;
 
-const MAX_HTTP_HEADER_LINE_LENGTH: usize = 4096;
-
 pub struct Req<'a> {
+    head: Cow<'a, str>,
     method: method::Method,
-    path: String,
-    version: String,
-    headers: collections::HashMap<String, String>,
-    body: &'a mut io::BufRead,
+    path: Span,
+    version: Span,
+    headers: Vec<(Span, Span)>,
+    content_length: Option<u64>,
+    body: &'a mut dyn io::BufRead,
+    body_remaining: u64,
+    interim_writer: Option<&'a mut dyn io::Write>,
+    continue_pending: bool,
+    conn: ConnInfo,
+    forwarded: Option<Forwarded>,
     // params: collections::HashMap<String, String>,
 }
 
 impl<'a> Req<'a> {
-    pub fn new(s: &'a mut io::BufRead) -> io::Result<Self> {
+    pub fn new(s: &'a mut dyn io::BufRead) -> Result<Self, ParseError> {
+        Self::with_limits(s, &Limits::default())
+    }
+
+    pub fn with_limits(s: &'a mut dyn io::BufRead, limits: &Limits) -> Result<Self, ParseError> {
+        let mut buf = Vec::new();
+        parser::read_head(s, &mut buf, limits)?;
+        let head = String::from_utf8(buf).map_err(|e| invalid_encoding(e.as_bytes()))?;
+        Self::from_head(Cow::Owned(head), s, limits)
+    }
+
+    /// Parses into `buf` instead of a fresh allocation, so one buffer can serve many requests.
+    /// The request's path and headers borrow from it.
+    pub fn with_buffer(s: &'a mut dyn io::BufRead, buf: &'a mut Vec<u8>, limits: &Limits) -> Result<Self, ParseError> {
+        buf.clear();
+        parser::read_head(s, buf, limits)?;
+        let buf: &'a Vec<u8> = buf;
+        let head = std::str::from_utf8(buf).map_err(|_| invalid_encoding(buf))?;
+        Self::from_head(Cow::Borrowed(head), s, limits)
+    }
+
+    fn from_head(head: Cow<'a, str>, s: &'a mut dyn io::BufRead, limits: &Limits) -> Result<Self, ParseError> {
+        let parsed = parser::parse_head(&head, limits)?;
+        let method_str = &head[parsed.method.0..parsed.method.1];
+        let method = match method::Method::from(method_str) {
+            method::Method::UNKNOWN => return Err(ParseError::UnknownMethod(method_str.to_string())),
+            m => m,
+        };
         let mut req = Req {
-            method: method::Method::UNKNOWN,
-            path: String::new(),
-            version: String::new(),
-            headers: collections::HashMap::new(),
+            head: Cow::Borrowed(""),
+            method,
+            path: parsed.target,
+            version: parsed.version,
+            headers: parsed.headers,
+            content_length: None,
             body: s,
+            body_remaining: 0,
+            interim_writer: None,
+            continue_pending: false,
+            conn: ConnInfo::default(),
+            forwarded: None,
         };
-        let first_line = read_new_line(req.body)?;
-        let mut iter = first_line.split_whitespace();
-        if let Some(mstr) = iter.next() {
-            match method::Method::from(mstr) {
-                method::Method::UNKNOWN => return Err(io::Error::new(io::ErrorKind::InvalidData, "tcp stream doesn't have valid http method")),
-                m => { req.method = m}
-            }
-        } else {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "tcp stream doesn't have valid http first line"));
+        req.head = head;
+        for (k, v) in req.headers() {
+            trace!("header parsed {}: {}", k, v);
         }
 
-        if let Some(path) = iter.next() {
-            req.path = path.to_string();
+        req.content_length = req.parse_content_length()?;
+        req.body_remaining = req.content_length.unwrap_or(0);
+        if limits.max_body_size.map(|max| req.body_remaining > max).unwrap_or(false) {
+            return Err(LimitError::BodyTooLarge.into());
         }
-
-        if let Some(version) = iter.next() {
-            req.version = version.to_string();
-        }
-        
-        req.parse_headers()?;
+        // Expect is only defined for HTTP/1.1 onwards
+        req.continue_pending = req.version() != "HTTP/1.0" && req.expects_continue();
 
         Ok(req)
     }
 
-    fn parse_headers(&mut self) -> io::Result<Option<()>> {
-        let line = read_new_line(self.body)?;
-        if line.is_empty() {
-            return Ok(None);
+    // every Content-Length field must carry the same plain decimal value. Bodies framed by
+    // Transfer-Encoding can't be read, and leaving them on the socket would desync the connection.
+    fn parse_content_length(&self) -> Result<Option<u64>, ParseError> {
+        if let Some(encoding) = self.header(HTTP_HEADER_TRANSFER_ENCODING) {
+            if self.header(HTTP_HEADER_CONTENT_LENGTH).is_some() {
+                return Err(ParseError::ConflictingLength);
+            }
+            return Err(ParseError::UnsupportedTransferEncoding(encoding.to_string()));
+        }
+        let mut res = None;
+        for v in self.header_all(HTTP_HEADER_CONTENT_LENGTH) {
+            let invalid = || ParseError::InvalidContentLength(v.to_string());
+            if v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid());
+            }
+            let len = v.parse::<u64>().map_err(|_| invalid())?;
+            if res.is_some() && res != Some(len) {
+                return Err(invalid());
+            }
+            res = Some(len);
         }
-        let (k, v) = split_header_line(line);
-        trace!("header parsed {}: {}", k, v);
-        self.headers.insert(k, v);
-        self.parse_headers()
+        Ok(res)
+    }
+
+    fn slice(&self, span: Span) -> &str {
+        &self.head[span.0..span.1]
     }
 
     pub fn method(&self) -> &method::Method {
         &self.method
     }
 
-    pub fn path(&self) -> &String {
-        &self.path
+    pub fn path(&self) -> &str {
+        self.slice(self.path)
     }
-}
 
-fn read_new_line(s: &mut io::BufRead) -> io::Result<String> {
-    let mut res = String::new();
-    s.read_line(&mut res)?;
+    pub fn version(&self) -> &str {
+        self.slice(self.version)
+    }
 
-    if res.len() < 2 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "each line should at least contain \"\\r\\n\" at the end"));
+    /// All header fields in the order they were received.
+    pub fn headers<'r>(&'r self) -> impl Iterator<Item = (&'r str, &'r str)> + 'r {
+        let head: &'r str = &self.head;
+        self.headers.iter().map(move |(k, v)| (&head[k.0..k.1], &head[v.0..v.1]))
     }
-    
-    while res.as_str()[res.len()-2..] != *"\r\n" {
-        if res.len() > MAX_HTTP_HEADER_LINE_LENGTH {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "exceeding max header line limit"));
+
+    /// The first header field named `key`, compared case-insensitively.
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v)
+    }
+
+    pub fn header_all<'r>(&'r self, key: &'r str) -> impl Iterator<Item = &'r str> + 'r {
+        self.headers()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    pub fn body(&mut self) -> Body<'_> {
+        let body = Body::new(&mut *self.body, &mut self.body_remaining);
+        match self.interim_writer.as_mut() {
+            Some(w) if self.continue_pending => body.with_continue(&mut **w, &mut self.continue_pending),
+            _ => body,
         }
-        let mut additional = String::new();
-        s.read_line(&mut additional)?;
-        res += additional.as_str();
     }
-    res.pop();
-    res.pop();
-    Ok(res)
-}
 
-fn split_header_line(line: String) -> (String, String) {
-    let mut k = String::new();
-    let mut v = String::new();
-    for (i, x) in line.splitn(2, ":").enumerate() {
-        if i == 0 { k = String::from(x.trim()); }
-        else { v = String::from(x.trim()); }
+    /// Where the interim `100 Continue` response is written when the client sent
+    /// `Expect: 100-continue`. It is sent the first time the body is read.
+    pub fn set_interim_writer(&mut self, w: &'a mut dyn io::Write) {
+        self.interim_writer = Some(w);
+    }
+
+    pub fn set_conn_info(&mut self, conn: ConnInfo) {
+        self.conn = conn;
+    }
+
+    pub fn conn_info(&self) -> &ConnInfo {
+        &self.conn
+    }
+
+    pub fn peer_addr(&self) -> Option<net::SocketAddr> {
+        self.conn.peer_addr
+    }
+
+    /// Honors `Forwarded`, or failing that `X-Forwarded-For/Proto/Host`, when the request came
+    /// from one of the `trusted` proxies. From anyone else those headers are ignored.
+    pub fn trust_proxies(&mut self, trusted: &[Cidr]) {
+        self.forwarded = None;
+        let immediate = match self.conn.proxied_addr.or(self.conn.peer_addr) {
+            Some(a) if !trusted.is_empty() => a.ip(),
+            _ => return,
+        };
+        let hops = match forwarded::parse_forwarded(self.header_all(HTTP_HEADER_FORWARDED)) {
+            Some(hops) if !hops.is_empty() => hops,
+            _ => {
+                let xff: Vec<&str> = self.header_all(HTTP_HEADER_X_FORWARDED_FOR).collect();
+                let proto: Vec<&str> = self.header_all(HTTP_HEADER_X_FORWARDED_PROTO).collect();
+                let host: Vec<&str> = self.header_all(HTTP_HEADER_X_FORWARDED_HOST).collect();
+                forwarded::parse_x_forwarded(&xff, &proto, &host)
+            },
+        };
+        self.forwarded = forwarded::resolve(immediate, &hops, trusted);
+    }
+
+    /// What trusted proxies reported, if `trust_proxies` found any.
+    pub fn forwarded(&self) -> Option<&Forwarded> {
+        self.forwarded.as_ref()
+    }
+
+    /// The address of the client. In order of preference: as reported by trusted proxies,
+    /// from a trusted PROXY protocol header, the peer's.
+    pub fn client_addr(&self) -> Option<net::SocketAddr> {
+        self.forwarded.as_ref().and_then(|f| f.client_addr)
+            .or(self.conn.proxied_addr)
+            .or(self.conn.peer_addr)
+    }
+
+    /// `http` or `https`, as reported by trusted proxies or else by how the connection arrived.
+    pub fn scheme(&self) -> &str {
+        match self.forwarded.as_ref().and_then(|f| f.scheme.as_deref()) {
+            Some(scheme) => scheme,
+            None if self.conn.tls => "https",
+            None => "http",
+        }
+    }
+
+    /// The host the client asked for, as reported by trusted proxies or else from `Host`.
+    pub fn host(&self) -> Option<&str> {
+        self.forwarded.as_ref().and_then(|f| f.host.as_deref())
+            .or_else(|| self.header(HTTP_HEADER_HOST))
+    }
+
+    pub fn local_addr(&self) -> Option<net::SocketAddr> {
+        self.conn.local_addr
+    }
+
+    pub fn connection_id(&self) -> u64 {
+        self.conn.id
+    }
+
+    /// 1 for the first request on a connection. 0 when the request was not read from a socket.
+    pub fn request_count(&self) -> u64 {
+        self.conn.request_count
+    }
+
+    pub fn is_tls(&self) -> bool {
+        self.conn.tls
+    }
+
+    pub fn expects_continue(&self) -> bool {
+        self.header(HTTP_HEADER_EXPECT)
+            .map(|e| e.eq_ignore_ascii_case("100-continue"))
+            .unwrap_or(false)
+    }
+
+    /// True if the request carries an `Expect` header other than `100-continue`.
+    pub fn has_unsupported_expectation(&self) -> bool {
+        self.header(HTTP_HEADER_EXPECT).is_some() && !self.expects_continue()
+    }
+
+    pub fn form(&mut self) -> Result<form::Form, form::FormError> {
+        self.form_with_limit(form::DEFAULT_FORM_SIZE_LIMIT)
+    }
+
+    pub fn form_with_limit(&mut self, limit: u64) -> Result<form::Form, form::FormError> {
+        match self.header(HTTP_HEADER_CONTENT_TYPE) {
+            Some(t) if form::is_media_type(t, form::FORM_CONTENT_TYPE) => {},
+            t => return Err(form::FormError::UnsupportedContentType(t.map(str::to_string))),
+        }
+        if self.body_remaining > limit {
+            return Err(form::FormError::TooLarge(limit));
+        }
+        let data = self.body().read_to_end_limited(limit)?;
+        form::Form::parse(&data)
+    }
+
+    pub fn multipart(&mut self) -> Result<multipart::Multipart<Body<'_>>, multipart::MultipartError> {
+        self.multipart_with_limits(multipart::DEFAULT_PART_SIZE_LIMIT, multipart::DEFAULT_MULTIPART_SIZE_LIMIT)
+    }
+
+    pub fn multipart_with_limits(&mut self, part_limit: u64, total_limit: u64) -> Result<multipart::Multipart<Body<'_>>, multipart::MultipartError> {
+        let boundary = match self.header(HTTP_HEADER_CONTENT_TYPE) {
+            Some(t) if form::is_media_type(t, multipart::MULTIPART_CONTENT_TYPE) => {
+                multipart::header_param(t, "boundary")
+                    .ok_or_else(|| multipart::MultipartError::InvalidBoundary(String::new()))?
+            },
+            t => return Err(multipart::MultipartError::UnsupportedContentType(t.map(str::to_string))),
+        };
+        if self.body_remaining > total_limit {
+            return Err(multipart::MultipartError::TooLarge(total_limit));
+        }
+        multipart::Multipart::with_limits(self.body(), &boundary, part_limit, total_limit)
+    }
+
+    pub fn json_value(&mut self) -> Result<json::JsonValue, json::JsonError> {
+        self.json_value_with_limit(json::DEFAULT_JSON_SIZE_LIMIT)
+    }
+
+    pub fn json_value_with_limit(&mut self, limit: u64) -> Result<json::JsonValue, json::JsonError> {
+        let data = self.json_body(limit)?;
+        json::JsonValue::parse_bytes(&data)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&mut self) -> Result<T, json::JsonError> {
+        self.json_with_limit(json::DEFAULT_JSON_SIZE_LIMIT)
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json_with_limit<T: serde::de::DeserializeOwned>(&mut self, limit: u64) -> Result<T, json::JsonError> {
+        let data = self.json_body(limit)?;
+        serde_json::from_slice(&data).map_err(|e| json::JsonError::Syntax(e.to_string()))
+    }
+
+    fn json_body(&mut self, limit: u64) -> Result<Vec<u8>, json::JsonError> {
+        match self.header(HTTP_HEADER_CONTENT_TYPE) {
+            Some(t) if json::is_json_content_type(t) => {},
+            t => return Err(json::JsonError::UnsupportedContentType(t.map(str::to_string))),
+        }
+        if self.body_remaining > limit {
+            return Err(json::JsonError::TooLarge(limit));
+        }
+        Ok(self.body().read_to_end_limited(limit)?)
+    }
+
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        self.header(HTTP_HEADER_COOKIE)
+            .map(cookie::parse_cookie_header)
+            .unwrap_or_default()
+    }
+
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().into_iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v)
     }
-    (k,v)
+}
+
+fn invalid_encoding(bytes: &[u8]) -> ParseError {
+    ParseError::InvalidHeader(String::from_utf8_lossy(bytes).into_owned())
 }
 
 #[cfg(test)]
@@ -117,13 +349,220 @@ mod tests {
         let mut buf = io::BufReader::new(http_req.as_bytes());
         let req = Req::new(&mut buf)?;
         assert_eq!(req.method, method::Method::GET);
-        assert_eq!(req.path, "/index.html");
-        assert_eq!(req.version, "HTTP/1.1");
-        assert_eq!(req.headers.len(), 3);
-        assert!(req.headers.contains_key("Host"));
-        assert!(req.headers.contains_key("Accept-Language"));
-        assert!(req.headers.contains_key("Host"));
-        assert_eq!(req.headers.get("Host").cloned(), Some(String::from("www.xiwen.com")));
+        assert_eq!(req.path(), "/index.html");
+        assert_eq!(req.version(), "HTTP/1.1");
+        assert_eq!(req.headers().count(), 3);
+        assert!(req.header("Host").is_some());
+        assert!(req.header("Accept-Language").is_some());
+        assert!(req.header("Host").is_some());
+        assert_eq!(req.header("Host"), Some("www.xiwen.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_body() -> io::Result<()> {
+        let http_req = String::from(HTTP_REQ_STR) + " trailing bytes";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        assert_eq!(req.content_length(), Some(5));
+        let mut body = String::new();
+        io::Read::read_to_string(&mut req.body(), &mut body)?;
+        assert_eq!(body, "Hello");
+        Ok(())
+    }
+
+    #[test]
+    fn test_form() -> io::Result<()> {
+        let http_req = "POST /login HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 27\r\n\r\nuser=xiwen&pass=p%40ss+word";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        let f = req.form().unwrap();
+        assert_eq!(f.get("user"), Some("xiwen"));
+        assert_eq!(f.get("pass"), Some("p@ss word"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_form_errors() -> io::Result<()> {
+        let http_req = "POST /login HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 3\r\n\r\na=b";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        assert_eq!(req.form().unwrap_err().status(), 415);
+
+        let http_req = "POST /login HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: 3\r\n\r\na=b";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        assert_eq!(req.form_with_limit(2).unwrap_err().status(), 413);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multipart() -> io::Result<()> {
+        let http_req = "POST /upload HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=\"b0undary\"\r\nContent-Length: 76\r\n\r\n\
+            --b0undary\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n1\r\n--b0undary--\r\nnext request";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        let mut mp = req.multipart().unwrap();
+        {
+            let mut part = mp.next_part().unwrap().unwrap();
+            assert_eq!(part.name(), Some("a"));
+            assert_eq!(part.text().unwrap(), "1");
+        }
+        assert!(mp.next_part().unwrap().is_none());
+        Ok(())
+    }
+
+    const JSON_REQ_STR: &str = "POST /items HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 24\r\n\r\n{\"id\": 7, \"tags\": [\"a\"]}";
+
+    #[test]
+    fn test_json_value() -> io::Result<()> {
+        let mut buf = io::BufReader::new(JSON_REQ_STR.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        let v = req.json_value().unwrap();
+        assert_eq!(v.get("id").and_then(json::JsonValue::as_i64), Some(7));
+
+        let http_req = "POST /items HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 5\r\n\r\n{\"id\"";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        assert_eq!(req.json_value().unwrap_err().status(), 400);
+        Ok(())
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_serde() -> io::Result<()> {
+        let mut buf = io::BufReader::new(JSON_REQ_STR.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        let v: serde_json::Value = req.json().unwrap();
+        assert_eq!(v["tags"][0], "a");
+        Ok(())
+    }
+
+    #[test]
+    fn test_expect_continue() -> io::Result<()> {
+        let http_req = "POST /upload HTTP/1.1\r\nExpect: 100-Continue\r\nContent-Length: 5\r\n\r\nHello";
+        let mut interim: Vec<u8> = vec![];
+        {
+            let mut buf = io::BufReader::new(http_req.as_bytes());
+            let mut req = Req::new(&mut buf)?;
+            req.set_interim_writer(&mut interim);
+            assert!(req.expects_continue());
+            assert!(!req.has_unsupported_expectation());
+            let mut body = String::new();
+            io::Read::read_to_string(&mut req.body(), &mut body)?;
+            io::Read::read_to_string(&mut req.body(), &mut body)?;
+            assert_eq!(body, "Hello");
+        }
+        assert_eq!(interim.as_slice(), b"HTTP/1.1 100 Continue\r\n\r\n");
+        Ok(())
+    }
+
+    fn limit_error(res: Result<Req, ParseError>) -> Option<LimitError> {
+        match res {
+            Err(ParseError::TooLarge(e)) => Some(e),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = Limits {
+            max_request_line: 32,
+            max_header_count: 2,
+            max_header_bytes: 48,
+            max_body_size: Some(4),
+        };
+        let long_line = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(32));
+        let mut buf = io::BufReader::new(long_line.as_bytes());
+        assert_eq!(limit_error(Req::with_limits(&mut buf, &limits)), Some(LimitError::RequestLineTooLong));
+
+        let mut buf = io::BufReader::new("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n".as_bytes());
+        assert_eq!(limit_error(Req::with_limits(&mut buf, &limits)), Some(LimitError::TooManyHeaders));
+
+        let big_headers = format!("GET / HTTP/1.1\r\nA: {}\r\nB: {}\r\n\r\n", "1".repeat(20), "2".repeat(20));
+        let mut buf = io::BufReader::new(big_headers.as_bytes());
+        assert_eq!(limit_error(Req::with_limits(&mut buf, &limits)), Some(LimitError::HeadersTooLarge));
+
+        let mut buf = io::BufReader::new("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHello".as_bytes());
+        assert_eq!(limit_error(Req::with_limits(&mut buf, &limits)), Some(LimitError::BodyTooLarge));
+
+        let mut buf = io::BufReader::new("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n".as_bytes());
+        assert!(Req::with_limits(&mut buf, &limits).is_ok());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = vec![
+            ("GET / HTTP/1.1\r\nHost: a", "ParseError::MissingCrlf"),
+            ("BREW /pot HTTP/1.1\r\n\r\n", "ParseError::UnknownMethod"),
+            ("GET /\r\n\r\n", "ParseError::InvalidRequestLine"),
+            ("GET / HTTP/1.1 extra\r\n\r\n", "ParseError::InvalidRequestLine"),
+            ("GET / HTTP/1.1\r\nno colon\r\n\r\n", "ParseError::InvalidHeader"),
+            ("GET / HTTP/1.1\r\n: empty name\r\n\r\n", "ParseError::InvalidHeader"),
+            ("GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", "ParseError::InvalidContentLength"),
+            ("GET / HTTP/1.1\r\nContent-Length: +1\r\n\r\n", "ParseError::InvalidContentLength"),
+            ("GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n", "ParseError::InvalidContentLength"),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n", "ParseError::UnsupportedTransferEncoding"),
+            ("POST / HTTP/1.1\r\ntransfer-encoding: gzip, chunked\r\n\r\n", "ParseError::UnsupportedTransferEncoding"),
+            ("POST / HTTP/1.1\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n", "ParseError::ConflictingLength"),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 5\r\n\r\n0\r\n\r\n", "ParseError::ConflictingLength"),
+            ("GET / HTTP/2.0\r\n\r\n", "ParseError::UnsupportedVersion"),
+            ("GET / HTTP/1.1\r\nBad\x01Value: 1\r\n\r\n", "ParseError::InvalidHeader"),
+            ("", "ParseError::Io"),
+        ];
+        for (raw, expected) in cases {
+            let mut buf = io::BufReader::new(raw.as_bytes());
+            let err = Req::new(&mut buf).err().map(|e| e.to_string()).unwrap_or_default();
+            assert!(err.starts_with(expected), "{:?} gave {:?}", raw, err);
+        }
+    }
+
+    #[test]
+    fn test_reused_buffer() -> io::Result<()> {
+        let mut head_buf = Vec::new();
+        for path in &["/first", "/second"] {
+            let raw = format!("GET {} HTTP/1.1\nHost: a\nX-Dup: 1\nx-dup: 2\n\n", path);
+            let mut buf = io::BufReader::new(raw.as_bytes());
+            let req = Req::with_buffer(&mut buf, &mut head_buf, &Limits::default())?;
+            assert_eq!(req.path(), *path);
+            assert_eq!(req.header_all("X-DUP").collect::<Vec<_>>(), vec!["1", "2"]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_trusted_proxies() -> io::Result<()> {
+        let raw = "GET / HTTP/1.1\r\nHost: internal:8080\r\nForwarded: for=192.0.2.60;proto=https;host=example.com\r\nX-Forwarded-For: 6.6.6.6\r\n\r\n";
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let peer = |ip: &str| ConnInfo { peer_addr: Some(net::SocketAddr::new(ip.parse().unwrap(), 1234)), ..ConnInfo::default() };
+
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        req.set_conn_info(peer("10.0.0.1"));
+        req.trust_proxies(&trusted);
+        assert_eq!(req.client_addr(), Some("192.0.2.60:0".parse().unwrap()));
+        assert_eq!(req.scheme(), "https");
+        assert_eq!(req.host(), Some("example.com"));
+
+        let mut buf = io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf)?;
+        req.set_conn_info(peer("192.0.2.99"));
+        req.trust_proxies(&trusted);
+        assert_eq!(req.client_addr(), Some("192.0.2.99:1234".parse().unwrap()));
+        assert_eq!(req.scheme(), "http");
+        assert_eq!(req.host(), Some("internal:8080"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cookies() -> io::Result<()> {
+        let http_req = "GET / HTTP/1.1\r\ncookie: sid=abc; theme=dark\r\n\r\n";
+        let mut buf = io::BufReader::new(http_req.as_bytes());
+        let req = Req::new(&mut buf)?;
+        assert_eq!(req.header("Cookie"), Some("sid=abc; theme=dark"));
+        assert_eq!(req.cookies().len(), 2);
+        assert_eq!(req.cookie("theme"), Some(String::from("dark")));
+        assert_eq!(req.cookie("missing"), None);
         Ok(())
     }
 }
\ No newline at end of file
@@ -2,17 +2,21 @@
 use {
     std::{
         collections,
-        io,
+        io, fs, path, net,
     },
-    super::headers::*,
+    super::{headers::*, cookie::{Cookie, CookieError}, date, json, mime, range::Segment, sendfile, status::StatusCode},
     crate::logger::help::*,
 };
 
 pub struct Res<'a> {
     version: String,
-    status_code: u16,
+    status_code: StatusCode,
     status: String,
     headers: collections::HashMap<&'a str, String>,
+    cookies: Vec<Cookie>,
+    auto_date: bool,
+    server: Option<String>,
+    raw_stream: Option<&'a net::TcpStream>,
     response_writer: &'a mut io::Write,
     responded: bool,
 }
@@ -21,9 +25,13 @@ impl<'a> Res<'a> {
     pub fn new(w: &'a mut io::Write) -> Self {
         Self {
             version: String::from("HTTP/1.x"),
-            status_code: 200,
+            status_code: StatusCode::OK,
             status: String::from("OK"),
             headers: collections::HashMap::new(),
+            cookies: vec![],
+            auto_date: true,
+            server: None,
+            raw_stream: None,
             response_writer: w,
             responded: false,
         }
@@ -33,34 +41,320 @@ impl<'a> Res<'a> {
         self.headers.insert(key, value.to_owned())
     }
 
-    pub fn set_status(&mut self, status_code: u16, status: &'static str) {
+    /// Adds a `Set-Cookie` header, replacing an earlier cookie with the same name, path and domain.
+    /// Cookies that could inject headers or attributes are rejected, see `Cookie::validate`.
+    pub fn set_cookie(&mut self, cookie: Cookie) -> Result<(), CookieError> {
+        cookie.validate()?;
+        self.cookies.retain(|c| !c.replaces(&cookie));
+        self.cookies.push(cookie);
+        Ok(())
+    }
+
+    /// Whether `respond` adds a `Date` header when the handler has not set one. On by default.
+    pub fn set_auto_date(&mut self, enabled: bool) {
+        self.auto_date = enabled;
+    }
+
+    /// The `Server` header added by `respond` unless the handler sets one. `None` leaves it out.
+    pub fn set_server(&mut self, server: Option<&str>) {
+        self.server = server.map(str::to_string);
+    }
+
+    /// The socket `w` ends up writing to. File responses are then handed to the kernel
+    /// (`sendfile` on Linux) instead of being copied through `w`.
+    pub fn set_raw_stream(&mut self, s: &'a net::TcpStream) {
+        self.raw_stream = Some(s);
+    }
+
+    fn has_header(&self, key: &str) -> bool {
+        self.headers.keys().any(|k| k.eq_ignore_ascii_case(key))
+    }
+
+    /// Sets the status with custom reason text. Codes outside `100..=999` are answered as `500`.
+    pub fn set_status(&mut self, status_code: u16, status: impl Into<String>) {
+        self.status_code = StatusCode::from_u16(status_code).unwrap_or_else(|| {
+            warn!("invalid HTTP status code {}, responding with 500", status_code);
+            StatusCode::INTERNAL_SERVER_ERROR
+        });
+        self.status = status.into();
+    }
+
+    /// Sets the status with its canonical reason phrase, or an empty one for unregistered codes.
+    pub fn set_status_code(&mut self, status_code: StatusCode) {
         self.status_code = status_code;
-        self.status = status.to_string();
+        self.status = status_code.canonical_reason().unwrap_or("").to_string();
     }
 
     pub fn respond(&mut self, content: &[u8]) -> io::Result<()> {
+        self.check_not_responded()?;
+        let content = if self.body_allowed() { content } else { &[] };
+        self.write_head(content.len() as u64)?;
+        self.response_writer.write_all(content)?;
+        self.response_writer.flush()?;
+        self.responded = true;
+        debug!("HTTP responded {} with {} bytes", self.status_code.as_u16(), content.len());
+        Ok(())
+    }
+
+    fn check_not_responded(&self) -> io::Result<()> {
         if self.responded {
             return Err(io::Error::new(io::ErrorKind::Other, "HTTP Already responded"));
         }
-        self.response_writer.write(format!("{} {} {}\r\n", self.version, self.status_code, self.status).as_bytes())?;
-        self.response_writer.write(format!("{}: {}\r\n", HTTP_HEADER_CONTENT_LENGTH, content.len()).as_bytes())?;
+        Ok(())
+    }
+
+    // 1xx, 204 and 304 responses carry neither a body nor Content-Length
+    fn body_allowed(&self) -> bool {
+        !(self.status_code.is_informational() || self.status_code == StatusCode::NO_CONTENT || self.status_code == StatusCode::NOT_MODIFIED)
+    }
+
+    fn write_head(&mut self, content_length: u64) -> io::Result<()> {
+        self.response_writer.write_all(format!("{} {} {}\r\n", self.version, self.status_code.as_u16(), self.status).as_bytes())?;
+        if self.auto_date && !self.has_header(HTTP_HEADER_DATE) {
+            self.response_writer.write_all(format!("{}: {}\r\n", HTTP_HEADER_DATE, date::http_date_now()).as_bytes())?;
+        }
+        if let Some(server) = self.server.as_ref().filter(|_| !self.has_header(HTTP_HEADER_SERVER)) {
+            self.response_writer.write_all(format!("{}: {}\r\n", HTTP_HEADER_SERVER, server).as_bytes())?;
+        }
+        if self.body_allowed() {
+            self.response_writer.write_all(format!("{}: {}\r\n", HTTP_HEADER_CONTENT_LENGTH, content_length).as_bytes())?;
+        }
         for (key, value) in self.headers.iter() {
-            self.response_writer.write(format!("{}: {}\r\n", key, value).as_bytes())?;
+            self.response_writer.write_all(format!("{}: {}\r\n", key, value).as_bytes())?;
+        }
+        for cookie in self.cookies.iter() {
+            self.response_writer.write_all(format!("{}: {}\r\n", HTTP_HEADER_SET_COOKIE, cookie).as_bytes())?;
         }
-        self.response_writer.write(b"\r\n")?;
-        self.response_writer.write(content)?;
+        self.response_writer.write_all(b"\r\n")
+    }
+
+    /// Responds with `status` and a `Location` header. `status` should be a 3xx code.
+    pub fn redirect(&mut self, status: StatusCode, location: &str) -> io::Result<()> {
+        if !status.is_redirection() {
+            warn!("redirecting to {} with non-redirect status {}", location, status);
+        }
+        self.set_status_code(status);
+        self.set_header(HTTP_HEADER_LOCATION, location);
+        self.set_header(HTTP_HEADER_CONTENT_TYPE, mime::TEXT_PLAIN);
+        self.respond(format!("Redirecting to {}", location).as_bytes())
+    }
+
+    pub fn text(&mut self, body: &str) -> io::Result<()> {
+        self.set_header(HTTP_HEADER_CONTENT_TYPE, mime::TEXT_PLAIN);
+        self.respond(body.as_bytes())
+    }
+
+    pub fn html(&mut self, body: &str) -> io::Result<()> {
+        self.set_header(HTTP_HEADER_CONTENT_TYPE, mime::TEXT_HTML);
+        self.respond(body.as_bytes())
+    }
+
+    /// Responds with `status` and no body, e.g. `StatusCode::NO_CONTENT`.
+    pub fn empty(&mut self, status: StatusCode) -> io::Result<()> {
+        self.set_status_code(status);
+        self.respond(&[])
+    }
+
+    /// Responds with the content of the file at `p`, typed by its extension unless
+    /// a `Content-Type` has already been set.
+    pub fn file(&mut self, p: impl AsRef<path::Path>) -> io::Result<()> {
+        self.check_not_responded()?;
+        let p = p.as_ref();
+        let file = fs::File::open(p)?;
+        let len = file.metadata()?.len();
+        if !self.has_header(HTTP_HEADER_CONTENT_TYPE) {
+            self.set_header(HTTP_HEADER_CONTENT_TYPE, mime::from_path(p));
+        }
+        self.respond_file(&file, len)
+    }
+
+    /// Responds with the first `len` bytes of `file` without loading it into memory.
+    pub fn respond_file(&mut self, file: &fs::File, len: u64) -> io::Result<()> {
+        self.respond_file_range(file, 0, len)
+    }
+
+    /// Responds with `len` bytes of `file` starting at `offset`, e.g. for a `206 Partial Content`.
+    pub fn respond_file_range(&mut self, file: &fs::File, offset: u64, len: u64) -> io::Result<()> {
+        self.respond_segments(&[Segment::File { file, offset, len }])
+    }
+
+    /// Responds with a body made of in-memory and file pieces, e.g. a `multipart/byteranges` one.
+    pub(crate) fn respond_segments(&mut self, segments: &[Segment]) -> io::Result<()> {
+        self.check_not_responded()?;
+        let body_allowed = self.body_allowed();
+        let len = if body_allowed { segments.iter().map(Segment::len).sum() } else { 0 };
+        self.write_head(len)?;
         self.response_writer.flush()?;
+        // from here on a failure leaves a truncated body, so the response counts as sent either way
         self.responded = true;
-        debug!("HTTP responded {} with {} bytes", self.status_code, content.len());
+        for segment in segments.iter().filter(|_| body_allowed) {
+            match *segment {
+                Segment::Bytes(ref b) => {
+                    self.response_writer.write_all(b)?;
+                    self.response_writer.flush()?;
+                },
+                Segment::File { len: 0, .. } => {},
+                Segment::File { file, offset, len } => match self.raw_stream {
+                    Some(s) => sendfile::send_file(file, s, offset, len)?,
+                    None => {
+                        sendfile::copy_file(file, &mut self.response_writer, offset, len)?;
+                        self.response_writer.flush()?;
+                    },
+                },
+            }
+        }
+        debug!("HTTP responded {} with {} bytes from file", self.status_code.as_u16(), len);
         Ok(())
     }
 
+    pub fn json_value(&mut self, value: &json::JsonValue) -> io::Result<()> {
+        self.set_header(HTTP_HEADER_CONTENT_TYPE, json::JSON_CONTENT_TYPE);
+        self.respond(value.to_string().as_bytes())
+    }
+
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        let body = serde_json::to_vec(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.set_header(HTTP_HEADER_CONTENT_TYPE, json::JSON_CONTENT_TYPE);
+        self.respond(&body)
+    }
+
     // getters:
     #[allow(unused)]
     pub fn status(&self) -> &String {
         &self.status
     }
+    pub fn status_code(&self) -> StatusCode {
+        self.status_code
+    }
     pub fn responded(&self) -> bool {
         self.responded
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        super::super::cookie::SameSite,
+    };
+
+    #[test]
+    fn json_error_responds_with_status() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        json::JsonError::Syntax("oops".to_string()).respond(&mut res).unwrap();
+        assert_eq!(res.status(), "Bad Request");
+        let written = String::from_utf8(write_buf).unwrap();
+        assert!(written.starts_with("HTTP/1.x 400 Bad Request\r\n"));
+        assert!(written.contains("Content-Type: application/json\r\n"));
+        assert!(written.ends_with("\r\n\r\n{\"error\":\"JsonError::Syntax: oops\"}"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn responds_serde_json() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.json(&vec![1, 2]).unwrap();
+        assert!(String::from_utf8(write_buf).unwrap().ends_with("\r\n\r\n[1,2]"));
+    }
+
+    #[test]
+    fn one_set_cookie_header_per_cookie() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
+        res.set_cookie(Cookie::new("sid", "old")).unwrap();
+        res.set_cookie(Cookie::new("sid", "admin").path("/admin")).unwrap();
+        res.set_cookie(Cookie::new("sid", "abc").http_only(true)).unwrap();
+        res.set_cookie(Cookie::new("theme", "dark").same_site(SameSite::Strict)).unwrap();
+        assert!(res.set_cookie(Cookie::new("theme", "dark\r\nLocation: //evil.com")).is_err());
+        res.respond(b"").unwrap();
+        assert_eq!(
+            write_buf.as_slice(),
+            "HTTP/1.x 200 OK\r\nContent-Length: 0\r\nSet-Cookie: sid=admin; Path=/admin\r\nSet-Cookie: sid=abc; HttpOnly\r\nSet-Cookie: theme=dark; SameSite=Strict\r\n\r\n".as_bytes(),
+        );
+    }
+
+    #[test]
+    fn adds_date_and_server_unless_overridden() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_server(Some("hermitcrab"));
+        res.respond(b"").unwrap();
+        let written = String::from_utf8(write_buf).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert!(lines[1].starts_with("Date: ") && lines[1].ends_with(" GMT"));
+        assert_eq!(lines[2], "Server: hermitcrab");
+
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_server(Some("hermitcrab"));
+        res.set_header("date", "Sun, 06 Nov 1994 08:49:37 GMT");
+        res.set_header(HTTP_HEADER_SERVER, "custom");
+        res.respond(b"").unwrap();
+        let written = String::from_utf8(write_buf).unwrap();
+        assert_eq!(written.matches("ate: ").count(), 1);
+        assert!(written.contains("date: Sun, 06 Nov 1994 08:49:37 GMT\r\n"));
+        assert_eq!(written.matches("Server: ").count(), 1);
+        assert!(written.contains("Server: custom\r\n"));
+    }
+
+    #[test]
+    fn status_with_canonical_or_custom_reason() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_status_code(StatusCode::NOT_FOUND);
+        assert_eq!(res.status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(res.status(), "Not Found");
+        res.set_status(429, format!("Slow down, retry in {}s", 3));
+        assert_eq!(res.status_code(), 429);
+        res.set_status(42, "Nonsense");
+        assert_eq!(res.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+        res.set_status(299, "Custom");
+        res.set_auto_date(false);
+        res.respond(b"").unwrap();
+        assert!(write_buf.starts_with(b"HTTP/1.x 299 Custom\r\n"));
+    }
+
+    fn respond_with(f: impl FnOnce(&mut Res) -> io::Result<()>) -> String {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
+        f(&mut res).unwrap();
+        assert!(res.responded());
+        assert!(res.text("again").is_err());
+        String::from_utf8(write_buf).unwrap()
+    }
+
+    #[test]
+    fn convenience_builders() {
+        let written = respond_with(|res| res.redirect(StatusCode::SEE_OTHER, "/login"));
+        assert!(written.starts_with("HTTP/1.x 303 See Other\r\n"));
+        assert!(written.contains("Location: /login\r\n"));
+
+        let written = respond_with(|res| res.text("hi"));
+        assert!(written.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(written.ends_with("\r\n\r\nhi"));
+
+        let written = respond_with(|res| res.html("<p>hi</p>"));
+        assert!(written.contains("Content-Type: text/html; charset=utf-8\r\n"));
+
+        let written = respond_with(|res| res.empty(StatusCode::NO_CONTENT));
+        assert_eq!(written, "HTTP/1.x 204 No Content\r\n\r\n");
+
+        let written = respond_with(|res| res.file("./test_data/assets/main.css"));
+        assert!(written.contains("Content-Type: text/css; charset=utf-8\r\n"));
+        assert!(written.contains("Content-Length: 33\r\n"));
+        assert!(written.ends_with("color: green;\n}"));
+    }
+
+    #[test]
+    fn missing_file_leaves_response_open() {
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        assert!(res.file("./test_data/missing.txt").is_err());
+        assert!(!res.responded());
+    }
+}
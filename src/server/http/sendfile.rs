@@ -0,0 +1,100 @@
+use std::{fs, io::{self, Seek}, net};
+
+/// Writes `len` bytes of `file` starting at `offset` to `socket`. On Linux the kernel copies them
+/// straight from the page cache with `sendfile(2)`; elsewhere, or when the kernel refuses,
+/// they go through a userspace buffer.
+pub(crate) fn send_file(file: &fs::File, socket: &net::TcpStream, offset: u64, len: u64) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        match linux::send_file(file, socket, offset, len) {
+            Ok(()) => return Ok(()),
+            Err(Fallback::Unsupported) => {},
+            Err(Fallback::Failed(e)) => return Err(e),
+        }
+    }
+    let mut socket = socket;
+    copy_file(file, &mut socket, offset, len)
+}
+
+/// Copies `len` bytes of `file` starting at `offset` through a userspace buffer.
+pub(crate) fn copy_file(mut file: &fs::File, w: &mut dyn io::Write, offset: u64, len: u64) -> io::Result<()> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    let copied = io::copy(&mut io::Read::take(file, len), w)?;
+    if copied < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while being sent"));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+enum Fallback {
+    // nothing was sent, a buffered copy can take over
+    Unsupported,
+    Failed(io::Error),
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use {
+        std::{convert::TryFrom, fs, io, net, os::unix::io::AsRawFd},
+        super::Fallback,
+    };
+
+    // the most a single sendfile call transfers on Linux
+    const MAX_CHUNK: u64 = 0x7fff_f000;
+
+    pub(super) fn send_file(file: &fs::File, socket: &net::TcpStream, offset: u64, len: u64) -> Result<(), Fallback> {
+        let mut offset = match libc::off_t::try_from(offset) {
+            Ok(offset) => offset,
+            Err(_) => return Err(Fallback::Unsupported),
+        };
+        let mut sent = 0u64;
+        while sent < len {
+            let count = (len - sent).min(MAX_CHUNK) as usize;
+            // SAFETY: both descriptors stay open for the duration of the call and `offset` is a valid pointer
+            let n = unsafe { libc::sendfile(socket.as_raw_fd(), file.as_raw_fd(), &mut offset, count) };
+            if n < 0 {
+                let e = io::Error::last_os_error();
+                match e.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(libc::EINVAL) | Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) if sent == 0 => return Err(Fallback::Unsupported),
+                    _ => return Err(Fallback::Failed(e)),
+                }
+            }
+            if n == 0 {
+                return Err(Fallback::Failed(io::Error::new(io::ErrorKind::UnexpectedEof, "file shrank while being sent")));
+            }
+            sent += n as u64;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{io::Read, thread},
+    };
+
+    #[test]
+    fn sends_file_over_socket() {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut received = String::new();
+            net::TcpStream::connect(addr).unwrap().read_to_string(&mut received).unwrap();
+            received
+        });
+        let (s, _) = listener.accept().unwrap();
+        let css = "#main-title {\n    color: green;\n}";
+        send_file(&fs::File::open("./test_data/assets/main.css").unwrap(), &s, 0, css.len() as u64).unwrap();
+        copy_file(&fs::File::open("./test_data/assets/main.css").unwrap(), &mut &s, 0, 5).unwrap();
+        send_file(&fs::File::open("./test_data/assets/main.css").unwrap(), &s, 1, 4).unwrap();
+        copy_file(&fs::File::open("./test_data/assets/main.css").unwrap(), &mut &s, 6, 5).unwrap();
+        // asking for more than the file holds is an error, after what it does hold was sent
+        assert!(send_file(&fs::File::open("./test_data/assets/main.css").unwrap(), &s, 0, 1024).is_err());
+        drop(s);
+        assert_eq!(client.join().unwrap(), format!("{}#mainmaintitle{}", css, css));
+    }
+}
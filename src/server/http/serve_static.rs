@@ -1,49 +1,711 @@
 use {
-    std::{io, path, fs, sync},
+    std::{io, path, fs, sync, time, borrow, collections::{self, HashMap}},
     super::{
         trie::Trie,
         method::Method,
         req::Req,
         res::Res,
+        status::StatusCode,
         handler::HandlerRef,
+        headers::*,
+        mime::MimeTypes,
+        conditional::{self, ETag, Validators},
+        cache::{self, CacheControl, CacheRule},
+        range,
+        autoindex::{Entry, Listing},
+        encoding::{self, ContentEncoding},
+        glob::Glob,
+        date,
+        url,
     },
     crate::logger::help::*,
 };
 
-pub fn add_directory_to_trie(prefix: &path::Path, dir: &path::Path, trie: &mut Trie) -> io::Result<()> {
+/// What to do when a request path leads through a symbolic link.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum SymlinkPolicy {
+    Follow,
+    Deny,
+    /// Follow links as long as the final target is still inside the served directory.
+    #[default]
+    FollowWithinRoot,
+}
+
+/// How static files get their `ETag`.
+#[derive(Copy, Clone, PartialEq, Debug, Default)]
+pub enum EtagStrategy {
+    /// A weak tag from size and modification time. Costs a `stat`, which is needed anyway.
+    #[default]
+    Metadata,
+    /// A strong tag from hashing the content, cached until size or modification time change.
+    ContentHash,
+    Disabled,
+}
+
+/// How a directory mounted with `Muxer::serve_static_with` is served.
+#[derive(Clone, Debug)]
+pub struct StaticOptions {
+    preload: bool,
+    symlinks: SymlinkPolicy,
+    allow_hidden: bool,
+    mime_types: Option<MimeTypes>,
+    nosniff: bool,
+    etag: EtagStrategy,
+    cache: Vec<CacheRule>,
+    vary: Vec<String>,
+    index: Option<String>,
+    autoindex: bool,
+    precompressed: Vec<ContentEncoding>,
+    spa_fallback: Option<String>,
+    spa_exclude: Vec<Glob>,
+}
+
+impl Default for StaticOptions {
+    fn default() -> Self {
+        Self {
+            preload: false,
+            symlinks: SymlinkPolicy::default(),
+            allow_hidden: false,
+            mime_types: None,
+            nosniff: false,
+            etag: EtagStrategy::default(),
+            cache: vec![],
+            vary: vec![],
+            index: Some(String::from("index.html")),
+            autoindex: false,
+            precompressed: ContentEncoding::ALL.to_vec(),
+            spa_fallback: None,
+            spa_exclude: vec![],
+        }
+    }
+}
+
+impl StaticOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads every file into memory when mounting instead of from disk per request.
+    /// Files added, changed or removed afterwards are not noticed.
+    pub fn preload(mut self, preload: bool) -> Self {
+        self.preload = preload;
+        self
+    }
+
+    pub fn is_preload(&self) -> bool {
+        self.preload
+    }
+
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Serves files and directories whose name starts with `.`. Off by default, such paths are `404`.
+    pub fn allow_hidden(mut self, allow: bool) -> Self {
+        self.allow_hidden = allow;
+        self
+    }
+
+    /// Content type overrides for this mount instead of the server-wide ones.
+    pub fn mime_types(mut self, types: MimeTypes) -> Self {
+        self.mime_types = Some(types);
+        self
+    }
+
+    /// Sends `X-Content-Type-Options: nosniff` so browsers stick to the declared content type.
+    pub fn nosniff(mut self, nosniff: bool) -> Self {
+        self.nosniff = nosniff;
+        self
+    }
+
+    pub fn etag(mut self, strategy: EtagStrategy) -> Self {
+        self.etag = strategy;
+        self
+    }
+
+    /// Sends `policy` as `Cache-Control`, plus a matching `Expires`, for files matching `pattern`.
+    /// Rules are tried in the order they were added; see `CacheRule::new` for the pattern syntax.
+    pub fn cache(self, pattern: &str, policy: CacheControl) -> Self {
+        self.cache_rule(CacheRule::new(pattern, policy))
+    }
+
+    pub fn cache_rule(mut self, rule: CacheRule) -> Self {
+        self.cache.push(rule);
+        self
+    }
+
+    /// Adds `header` to the `Vary` header of every response, for when something in front of
+    /// the mount picks the response by that request header.
+    pub fn vary(mut self, header: &str) -> Self {
+        if !self.vary.iter().any(|h| h.eq_ignore_ascii_case(header)) {
+            self.vary.push(header.to_string());
+        }
+        self
+    }
+
+    /// The file served for a directory path, `index.html` by default. `None` turns index files off.
+    pub fn index(mut self, name: Option<&str>) -> Self {
+        self.index = name.map(str::to_string);
+        self
+    }
+
+    /// Lists the content of directories that have no index file, as HTML or as JSON
+    /// to clients that ask for `application/json`. Off by default, such paths are `404`.
+    pub fn autoindex(mut self, autoindex: bool) -> Self {
+        self.autoindex = autoindex;
+        self
+    }
+
+    /// The precompressed siblings looked for next to each file, e.g. `app.js.br` and `app.js.gz` for `app.js`,
+    /// in order of preference. The best one the client accepts is sent with `Content-Encoding`.
+    /// Brotli and gzip by default; an empty list turns this off.
+    pub fn precompressed(mut self, encodings: &[ContentEncoding]) -> Self {
+        self.precompressed = encodings.to_vec();
+        self
+    }
+
+    /// Single-page application mode: paths under the mount that match no file are answered with `fallback`,
+    /// a file relative to the served directory such as `index.html`, so client-side routing can take over.
+    /// Paths whose last segment has an extension are taken for missing assets and still get `404`.
+    pub fn spa(mut self, fallback: &str) -> Self {
+        self.spa_fallback = Some(fallback.trim_start_matches('/').to_string());
+        self
+    }
+
+    /// Keeps paths matching `pattern`, relative to the mount (see `Glob::new`), from falling back
+    /// in single-page application mode, e.g. `/api/**`, which covers `/api` itself too.
+    pub fn spa_exclude(mut self, pattern: &str) -> Self {
+        self.spa_exclude.push(Glob::new(pattern));
+        self
+    }
+
+    pub(crate) fn spa_fallback(&self) -> Option<&str> {
+        self.spa_fallback.as_deref()
+    }
+
+    // The SPA fallback for `relative`, a path under the mount that matched nothing, if it gets one.
+    pub(crate) fn falls_back(&self, relative: &str) -> Option<&str> {
+        let fallback = self.spa_fallback.as_ref()?;
+        let segments = normalize_path(relative)?;
+        let relative = segments.join("/");
+        let has_extension = segments.last().map(|s| path::Path::new(s).extension().is_some()).unwrap_or(false);
+        if has_extension || self.spa_exclude.iter().any(|g| g.matches(&relative)) {
+            return None;
+        }
+        if !self.allow_hidden && segments.iter().any(|s| s.starts_with('.')) {
+            return None;
+        }
+        Some(fallback)
+    }
+
+    pub(crate) fn or_mime_types(mut self, types: &MimeTypes) -> Self {
+        if self.mime_types.is_none() {
+            self.mime_types = Some(types.clone());
+        }
+        self
+    }
+
+    // `relative` is the file's path under the served directory, `negotiated` whether it has precompressed
+    // siblings. These go on `304`s as well.
+    fn set_cache_headers(&self, relative: &path::Path, negotiated: bool, res: &mut Res) {
+        if let Some(policy) = cache::find_policy(&self.cache, relative) {
+            res.set_header(HTTP_HEADER_CACHE_CONTROL, &policy.to_string());
+            if let Some(expires) = policy.expires(time::SystemTime::now()) {
+                res.set_header(HTTP_HEADER_EXPIRES, &date::fmt_http_date(expires));
+            }
+        }
+        self.set_vary(if negotiated { Some(HTTP_HEADER_ACCEPT_ENCODING) } else { None }, res);
+    }
+
+    // the configured `Vary` plus the header the response was negotiated on
+    fn set_vary(&self, negotiated_on: Option<&str>, res: &mut Res) {
+        let mut vary = self.vary.clone();
+        if let Some(header) = negotiated_on.filter(|h| !vary.iter().any(|v| v.eq_ignore_ascii_case(h))) {
+            vary.push(header.to_string());
+        }
+        if !vary.is_empty() {
+            res.set_header(HTTP_HEADER_VARY, &vary.join(", "));
+        }
+    }
+
+    fn content_type(&self, p: &path::Path) -> String {
+        match &self.mime_types {
+            Some(types) => types.lookup(p),
+            None => super::mime::from_path(p).to_string(),
+        }
+    }
+
+    // returns the content type it set
+    fn set_content_headers(&self, p: &path::Path, res: &mut Res) -> String {
+        let content_type = self.content_type(p);
+        res.set_header(HTTP_HEADER_CONTENT_TYPE, &content_type);
+        if self.nosniff {
+            res.set_header(HTTP_HEADER_X_CONTENT_TYPE_OPTIONS, "nosniff");
+        }
+        content_type
+    }
+
+    // listings are negotiated on `Accept`
+    fn set_listing_headers(&self, res: &mut Res) {
+        self.set_vary(Some(HTTP_HEADER_ACCEPT), res);
+        if self.nosniff {
+            res.set_header(HTTP_HEADER_X_CONTENT_TYPE_OPTIONS, "nosniff");
+        }
+    }
+}
+
+/// Serves files under `root` for request paths under `prefix`, looking each one up on disk per request.
+pub struct StaticDir {
+    prefix: String,
+    root: path::PathBuf,
+    // `root` with links resolved, for the within-root check
+    canonical_root: path::PathBuf,
+    options: StaticOptions,
+    // content hashes by path, valid while size and modification time match
+    hashes: sync::Mutex<HashMap<path::PathBuf, (u64, time::SystemTime, ETag)>>,
+}
+
+impl StaticDir {
+    pub fn new(prefix: &str, root: impl Into<path::PathBuf>, options: StaticOptions) -> io::Result<Self> {
+        let root = root.into();
+        if !root.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{} is not a directory", root.display())));
+        }
+        Ok(Self {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            canonical_root: root.canonicalize()?,
+            root,
+            options,
+            hashes: sync::Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn options(&self) -> &StaticOptions {
+        &self.options
+    }
+
+    /// True if `target`, a request target, falls under the mount prefix.
+    pub fn matches(&self, target: &str) -> bool {
+        under_prefix(&self.prefix, target).is_some()
+    }
+
+    /// The file a request target maps to, or `None` if it is outside the mount, cannot be a path in it,
+    /// or is ruled out by the hidden file or symlink policy.
+    pub fn resolve(&self, target: &str) -> Option<path::PathBuf> {
+        if !self.matches(target) {
+            return None;
+        }
+        let segments = normalize_path(&strip_query(target)[self.prefix.len()..])?;
+        if !self.options.allow_hidden && segments.iter().any(|s| s.starts_with('.')) {
+            return None;
+        }
+        let mut p = self.root.clone();
+        for segment in segments.iter() {
+            p.push(segment);
+            if self.options.symlinks == SymlinkPolicy::Deny && fs::symlink_metadata(&p).map(|m| m.file_type().is_symlink()).unwrap_or(false) {
+                return None;
+            }
+        }
+        if self.options.symlinks == SymlinkPolicy::FollowWithinRoot {
+            // paths that do not exist are let through; there is nothing to serve at them anyway
+            if let Ok(real) = p.canonicalize() {
+                if !real.starts_with(&self.canonical_root) {
+                    return None;
+                }
+            }
+        }
+        Some(p)
+    }
+
+    pub fn serve(&self, req: &Req, res: &mut Res) -> io::Result<()> {
+        match self.resolve(req.path()) {
+            Some(p) if p.is_file() => self.serve_file(req, &p, res),
+            Some(p) if p.is_dir() => self.serve_dir(req, &p, res),
+            Some(_) => self.serve_fallback(req, res),
+            None => not_found(res),
+        }
+    }
+
+    // Answers a path that is under the mount but has nothing to serve.
+    fn serve_fallback(&self, req: &Req, res: &mut Res) -> io::Result<()> {
+        let fallback = under_prefix(&self.prefix, req.path())
+            .and_then(|relative| self.options.falls_back(relative))
+            .and_then(|fallback| self.resolve(&format!("{}/{}", self.prefix, encode_path(fallback))))
+            .filter(|p| p.is_file());
+        match fallback {
+            Some(p) => self.serve_file(req, &p, res),
+            None => not_found(res),
+        }
+    }
+
+    fn serve_file(&self, req: &Req, p: &path::Path, res: &mut Res) -> io::Result<()> {
+        let mut sidecars = self.sidecars(p);
+        self.options.set_cache_headers(p.strip_prefix(&self.root).unwrap_or(p), !sidecars.is_empty(), res);
+        let available: Vec<ContentEncoding> = sidecars.iter().map(|(e, _)| *e).collect();
+        let chosen = encoding::negotiate(req.header(HTTP_HEADER_ACCEPT_ENCODING), &available)
+            .and_then(|e| sidecars.drain(..).find(|(s, _)| *s == e));
+        let (file, validators) = match &chosen {
+            Some((encoding, sidecar)) => (sidecar.as_path(), for_encoding(self.validators(sidecar)?, *encoding)),
+            None => (p, self.validators(p)?),
+        };
+        if validators.respond_if_unchanged(req, res)? {
+            return Ok(());
+        }
+        let content_type = self.options.set_content_headers(p, res);
+        if let Some((encoding, _)) = chosen {
+            res.set_header(HTTP_HEADER_CONTENT_ENCODING, encoding.token());
+        }
+        self.send_file(req, file, &validators, &content_type, res)
+    }
+
+    // The precompressed siblings of `p` that exist and that the symlink policy lets through.
+    fn sidecars(&self, p: &path::Path) -> Vec<(ContentEncoding, path::PathBuf)> {
+        self.options.precompressed.iter()
+            .map(|e| (*e, e.sidecar(p)))
+            .filter(|(_, sidecar)| sidecar.is_file() && self.sibling_allowed(sidecar))
+            .collect()
+    }
+
+    // `resolve` checked the path leading to the sibling, so only the sibling itself is left to check
+    fn sibling_allowed(&self, p: &path::Path) -> bool {
+        match self.options.symlinks {
+            SymlinkPolicy::Follow => true,
+            SymlinkPolicy::Deny => !fs::symlink_metadata(p).map(|m| m.file_type().is_symlink()).unwrap_or(true),
+            SymlinkPolicy::FollowWithinRoot => p.canonicalize().map(|real| real.starts_with(&self.canonical_root)).unwrap_or(false),
+        }
+    }
+
+    // Directories are only served at paths ending in `/`, so relative links in their index work.
+    fn serve_dir(&self, req: &Req, p: &path::Path, res: &mut Res) -> io::Result<()> {
+        let target = req.path();
+        let dir_path = strip_query(target);
+        if !dir_path.ends_with('/') {
+            let segments = under_prefix(&self.prefix, target).and_then(normalize_path).unwrap_or_default();
+            let location = directory_path(&self.prefix, segments.iter().map(|s| s.as_str()));
+            return res.redirect(StatusCode::MOVED_PERMANENTLY, &format!("{}{}", location, &target[dir_path.len()..]));
+        }
+        // going through `resolve` applies the hidden file and symlink policies to the index file too
+        let index = self.options.index.as_ref().and_then(|name| self.resolve(&format!("{}{}", dir_path, url::percent_encode(name))));
+        if let Some(index) = index.filter(|i| i.is_file()) {
+            return self.serve_file(req, &index, res);
+        }
+        if !self.options.autoindex {
+            return self.serve_fallback(req, res);
+        }
+        let mut entries = vec![];
+        for entry in fs::read_dir(p)? {
+            let name = match entry?.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            if let Some(entry_path) = self.resolve(&format!("{}{}", dir_path, url::percent_encode(&name))) {
+                if let Ok(entry) = Entry::from_path(name, &entry_path) {
+                    entries.push(entry);
+                }
+            }
+        }
+        let decoded = String::from_utf8_lossy(&url::percent_decode(dir_path.as_bytes(), false).unwrap_or_default()).into_owned();
+        self.options.set_listing_headers(res);
+        Listing::new(&decoded, entries, dir_path.len() > self.prefix.len() + 1).respond(req, res)
+    }
+
+    /// `ETag` and `Last-Modified` of the file at `p` as it is on disk now.
+    pub fn validators(&self, p: &path::Path) -> io::Result<Validators> {
+        let meta = fs::metadata(p)?;
+        let modified = meta.modified().ok();
+        let etag = match (self.options.etag, modified) {
+            (EtagStrategy::Disabled, _) => None,
+            (EtagStrategy::Metadata, Some(modified)) => Some(ETag::from_metadata(meta.len(), modified)),
+            (EtagStrategy::ContentHash, Some(modified)) => Some(self.content_hash(p, meta.len(), modified)?),
+            (_, None) => Some(ETag::from_hash(hash_file(p)?)),
+        };
+        Ok(Validators::new(etag, modified))
+    }
+
+    fn content_hash(&self, p: &path::Path, len: u64, modified: time::SystemTime) -> io::Result<ETag> {
+        if let Some((l, m, etag)) = self.hashes.lock().unwrap().get(p) {
+            if *l == len && *m == modified {
+                return Ok(etag.clone());
+            }
+        }
+        let etag = ETag::from_hash(hash_file(p)?);
+        self.hashes.lock().unwrap().insert(p.to_path_buf(), (len, modified, etag.clone()));
+        Ok(etag)
+    }
+
+    fn send_file(&self, req: &Req, p: &path::Path, validators: &Validators, content_type: &str, res: &mut Res) -> io::Result<()> {
+        let opened = fs::File::open(p).and_then(|f| f.metadata().map(|meta| (f, meta.len())));
+        match opened {
+            Ok((file, len)) => range::respond_file(req, res, validators, &file, len, content_type),
+            Err(e) => {
+                warn!("failed to open static file {}: {}", p.display(), e);
+                res.empty(StatusCode::NOT_FOUND)
+            },
+        }
+    }
+}
+
+// Each encoding is a representation of its own and must not share an entity tag with the others.
+fn for_encoding(validators: Validators, encoding: ContentEncoding) -> Validators {
+    Validators::new(validators.etag.map(|etag| etag.with_suffix(encoding.token())), validators.last_modified)
+}
+
+fn not_found(res: &mut Res) -> io::Result<()> {
+    res.set_status_code(StatusCode::NOT_FOUND);
+    res.text("Not Found")
+}
+
+fn hash_file(p: &path::Path) -> io::Result<u64> {
+    let mut f = fs::File::open(p)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut hash = conditional::FNV_OFFSET;
+    loop {
+        match io::Read::read(&mut f, &mut buf)? {
+            0 => return Ok(hash),
+            n => hash = conditional::fnv1a_extend(hash, &buf[..n]),
+        }
+    }
+}
+
+/// Splits a request path into the segments of a file path under the served directory,
+/// percent-decoding each one and resolving `.`, `..` and empty segments. `None` if the path would
+/// climb above the directory or contains NUL, a backslash, or an encoded `/`.
+pub fn normalize_path(p: &str) -> Option<Vec<String>> {
+    let mut segments: Vec<String> = vec![];
+    for raw in p.split('/') {
+        let segment = String::from_utf8(url::percent_decode(raw.as_bytes(), false)?).ok()?;
+        if segment.contains(['/', '\\', '\0']) {
+            return None;
+        }
+        match segment.as_str() {
+            "" | "." => {},
+            ".." => {
+                segments.pop()?;
+            },
+            _ => segments.push(segment),
+        }
+    }
+    Some(segments)
+}
+
+// The part of `target` after a mount `prefix` given without trailing `/`, if `target` is under it.
+pub(crate) fn under_prefix<'t>(prefix: &str, target: &'t str) -> Option<&'t str> {
+    let p = strip_query(target);
+    let relative = p.strip_prefix(prefix)?;
+    if relative.is_empty() || relative.starts_with('/') {
+        Some(relative)
+    } else {
+        None
+    }
+}
+
+// The path of the directory at `segments` under `prefix`, encoded and with a trailing `/`. Built from
+// the mount rather than from the request target, and never starting with `//`, which browsers read as
+// a link to another host.
+fn directory_path<'s>(prefix: &str, segments: impl Iterator<Item = &'s str>) -> String {
+    let mut p = String::from("/");
+    for segment in prefix.split('/').filter(|s| !s.is_empty()).map(String::from).chain(segments.map(url::percent_encode)) {
+        p.push_str(&segment);
+        p.push('/');
+    }
+    p
+}
+
+fn encode_path(p: &str) -> String {
+    p.split('/').map(url::percent_encode).collect::<Vec<String>>().join("/")
+}
+
+fn strip_query(target: &str) -> &str {
+    target.find(['?', '#']).map(|i| &target[..i]).unwrap_or(target)
+}
+
+/// A file kept in memory, for preloaded and embedded directories.
+#[derive(Clone, Debug)]
+pub(crate) struct MemoryFile {
+    data: borrow::Cow<'static, [u8]>,
+    modified: Option<time::SystemTime>,
+}
+
+impl MemoryFile {
+    pub(crate) fn new(data: impl Into<borrow::Cow<'static, [u8]>>, modified: Option<time::SystemTime>) -> Self {
+        Self { data: data.into(), modified }
+    }
+}
+
+// Files by their `/` separated path under the served directory.
+pub(crate) type MemoryTree = collections::BTreeMap<String, sync::Arc<MemoryFile>>;
+
+pub fn add_directory_to_trie(prefix: &path::Path, dir: &path::Path, trie: &mut Trie, options: &StaticOptions) -> io::Result<()> {
+    let prefix = prefix.to_str()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Path: {} can't be converted to utf8", prefix.display())))?;
+    add_tree_to_trie(prefix, &read_tree(dir, options)?, trie, options);
+    Ok(())
+}
+
+// Reads every file under `dir` the options allow into memory.
+pub(crate) fn read_tree(dir: &path::Path, options: &StaticOptions) -> io::Result<MemoryTree> {
+    let mut files = MemoryTree::new();
     if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                let _new_prefix = path.file_name();
-                let new_prefix = path.strip_prefix(dir).ok()
-                    .map(|p| prefix.join(p))
-                    .ok_or(io::Error::new(io::ErrorKind::Other, "Failed to add directory to trie"))?;
-                add_directory_to_trie(&new_prefix, &path, trie)?;
-            } else {
-                add_file_to_trie(&prefix, &path, trie)?;
+        read_subtree(dir, &dir.canonicalize()?, "", options, &mut files)?;
+    }
+    Ok(files)
+}
+
+// `root` is the served directory with links resolved, for the within-root check.
+fn read_subtree(dir: &path::Path, root: &path::Path, relative: &str, options: &StaticOptions, files: &mut MemoryTree) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => {
+                warn!("skipping static file {}: name is not utf8", path.display());
+                continue;
+            },
+        };
+        if !options.allow_hidden && name.starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_symlink() {
+            let allowed = match options.symlinks {
+                SymlinkPolicy::Follow => true,
+                SymlinkPolicy::Deny => false,
+                SymlinkPolicy::FollowWithinRoot => path.canonicalize().map(|real| real.starts_with(root)).unwrap_or(false),
+            };
+            if !allowed {
+                continue;
             }
         }
+        let relative = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+        if path.is_dir() {
+            read_subtree(&path, root, &relative, options, files)?;
+        } else {
+            let data = fs::read(&path)?;
+            let modified = fs::metadata(&path)?.modified().ok();
+            files.insert(relative, sync::Arc::new(MemoryFile::new(data, modified)));
+        }
     }
-    return Ok(());
+    Ok(())
 }
 
-fn add_file_to_trie(prefix: &path::Path, file: &path::Path, trie: &mut Trie) -> io::Result<()> {
-    let data = fs::read(file)?;
-    let owned_file = file.to_owned();
-    let p = file.file_name()
-        .map(|fname| prefix.join(fname))
-        .and_then(|p| p.to_str().map(|s| s.to_owned()))
-        .ok_or(io::Error::new(io::ErrorKind::InvalidData, format!("Path: {} can't be converted to utf8", file.display())))
-        .unwrap();
-    let hr: HandlerRef = sync::Arc::new(sync::Mutex::new(move|_: &mut Req, res:&mut Res| {
-        if let Err(e) = res.respond(data.as_slice()) {
-            error!("failed to respond static file: {} error: {}", owned_file.display(), e);
+// Registers a handler for every file in `files` under `prefix`, and for every directory they are in
+// a redirect to the path with a trailing `/`, and at that path the index file or a listing.
+pub(crate) fn add_tree_to_trie(prefix: &str, files: &MemoryTree, trie: &mut Trie, options: &StaticOptions) {
+    let prefix = prefix.trim_end_matches('/');
+    let files: MemoryTree = files.iter()
+        .filter(|(relative, _)| options.allow_hidden || !relative.split('/').any(|s| s.starts_with('.')))
+        .map(|(relative, file)| (relative.clone(), file.clone()))
+        .collect();
+    let mut dirs: collections::BTreeMap<String, Vec<Entry>> = collections::BTreeMap::new();
+    dirs.insert(String::new(), vec![]);
+    for (relative, file) in files.iter() {
+        if let Some(hr) = memory_file_handler(relative, &files, options) {
+            trie.insert(&format!("{}/{}", prefix, relative), &Method::GET, &hr);
+        }
+        let (dir, name) = split_parent(relative);
+        dirs.entry(dir.to_string()).or_default()
+            .push(Entry { name: name.to_string(), is_dir: false, size: file.data.len() as u64, modified: file.modified });
+        // list each directory in its parent, once
+        let mut child = dir;
+        while !child.is_empty() {
+            let (parent, name) = split_parent(child);
+            let entries = dirs.entry(parent.to_string()).or_default();
+            if entries.iter().any(|e| e.is_dir && e.name == name) {
+                break;
+            }
+            entries.push(Entry { name: name.to_string(), is_dir: true, size: 0, modified: None });
+            child = parent;
         }
-    }));
-    trie.insert(&p, &Method::GET, &hr);
-    return Ok(())
+    }
+    for (dir, entries) in dirs {
+        add_directory_index_to_trie(prefix, &dir, entries, &files, trie, options);
+    }
+}
+
+fn split_parent(relative: &str) -> (&str, &str) {
+    match relative.rfind('/') {
+        Some(i) => (&relative[..i], &relative[i + 1..]),
+        None => ("", relative),
+    }
+}
+
+fn add_directory_index_to_trie(prefix: &str, dir: &str, entries: Vec<Entry>, files: &MemoryTree, trie: &mut Trie, options: &StaticOptions) {
+    let key = if dir.is_empty() { prefix.to_string() } else { format!("{}/{}", prefix, dir) };
+    let dir_key = format!("{}/", key);
+    if !key.is_empty() {
+        let location = directory_path(prefix, dir.split('/').filter(|s| !s.is_empty()));
+        let redirect: HandlerRef = sync::Arc::new(sync::Mutex::new(move |req: &mut Req, res: &mut Res| {
+            let target = req.path();
+            let query = &target[strip_query(target).len()..];
+            if let Err(e) = res.redirect(StatusCode::MOVED_PERMANENTLY, &format!("{}{}", location, query)) {
+                error!("failed to redirect to directory {}: {}", location, e);
+            }
+        }));
+        trie.insert(&key, &Method::GET, &redirect);
+    }
+    let index = options.index.as_ref()
+        .filter(|name| options.allow_hidden || !name.starts_with('.'))
+        .and_then(|name| memory_file_handler(&if dir.is_empty() { name.clone() } else { format!("{}/{}", dir, name) }, files, options));
+    if let Some(hr) = index {
+        trie.insert(&dir_key, &Method::GET, &hr);
+    } else if options.autoindex {
+        let listing = Listing::new(&dir_key, entries, !dir.is_empty());
+        let options = options.clone();
+        let hr: HandlerRef = sync::Arc::new(sync::Mutex::new(move |req: &mut Req, res: &mut Res| {
+            options.set_listing_headers(res);
+            if let Err(e) = listing.respond(req, res) {
+                error!("failed to respond directory listing: {}", e);
+            }
+        }));
+        trie.insert(&dir_key, &Method::GET, &hr);
+    }
+}
+
+// A handler for the file at `relative` in `files`, negotiating its precompressed siblings there.
+// `None` if there is no such file.
+pub(crate) fn memory_file_handler(relative: &str, files: &MemoryTree, options: &StaticOptions) -> Option<HandlerRef> {
+    let file = files.get(relative)?.clone();
+    let options = options.clone();
+    let validators = memory_validators(&file, &options);
+    let sidecars: Vec<(ContentEncoding, sync::Arc<MemoryFile>, Validators)> = options.precompressed.iter()
+        .filter_map(|encoding| {
+            let sidecar = files.get(&format!("{}.{}", relative, encoding.extension()))?;
+            Some((*encoding, sidecar.clone(), for_encoding(memory_validators(sidecar, &options), *encoding)))
+        })
+        .collect();
+    let available: Vec<ContentEncoding> = sidecars.iter().map(|(e, _, _)| *e).collect();
+    let relative = path::PathBuf::from(relative);
+    Some(sync::Arc::new(sync::Mutex::new(move|req: &mut Req, res:&mut Res| {
+        options.set_cache_headers(&relative, !sidecars.is_empty(), res);
+        let chosen = encoding::negotiate(req.header(HTTP_HEADER_ACCEPT_ENCODING), &available)
+            .and_then(|e| sidecars.iter().find(|(s, _, _)| *s == e));
+        let (data, validators) = match chosen {
+            Some((_, sidecar, validators)) => (&sidecar.data, validators),
+            None => (&file.data, &validators),
+        };
+        match validators.respond_if_unchanged(req, res) {
+            Ok(false) => {},
+            Ok(true) => return,
+            Err(e) => return error!("failed to respond static file: {} error: {}", relative.display(), e),
+        }
+        let content_type = options.set_content_headers(&relative, res);
+        if let Some((encoding, _, _)) = chosen {
+            res.set_header(HTTP_HEADER_CONTENT_ENCODING, encoding.token());
+        }
+        if let Err(e) = range::respond_bytes(req, res, validators, data, &content_type) {
+            error!("failed to respond static file: {} error: {}", relative.display(), e);
+        }
+    })))
+}
+
+fn memory_validators(file: &MemoryFile, options: &StaticOptions) -> Validators {
+    let etag = match options.etag {
+        EtagStrategy::Disabled => None,
+        EtagStrategy::Metadata if file.modified.is_some() => file.modified.map(|m| ETag::from_metadata(file.data.len() as u64, m)),
+        _ => Some(ETag::from_content(&file.data)),
+    };
+    Validators::new(etag, file.modified)
 }
 
 
@@ -58,7 +720,7 @@ mod serve_static_tests {
     fn test_static() {
         let mut t = Trie::default();
         let p = path::Path::new("./test_data");
-        assert!(add_directory_to_trie(path::Path::new("/"), &p, &mut t).is_ok());
+        assert!(add_directory_to_trie(path::Path::new("/"), &p, &mut t, &StaticOptions::new()).is_ok());
         t._print();
         let mut css_handler = t.get("/assets/main.css", &Method::GET).unwrap();
 
@@ -67,10 +729,407 @@ mod serve_static_tests {
 
         let mut write_buf: Vec<u8> = vec![];
         let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
 
         css_handler.handle(&mut req, &mut res);
         let css = String::from_utf8(write_buf.clone()).unwrap();
         println!("css:\n {}", css);
-        assert_eq!(write_buf.as_slice(), "HTTP/1.x 200 OK\r\nContent-Length: 33\r\n\r\n#main-title {\n    color: green;\n}".as_bytes());
+        assert!(css.starts_with("HTTP/1.x 200 OK\r\n"));
+        assert!(css.contains("Content-Length: 33\r\n"));
+        assert!(css.contains("Content-Type: text/css; charset=utf-8\r\n"));
+        assert!(css.contains("ETag: W/\""));
+        assert!(css.contains("Last-Modified: "));
+        assert!(css.ends_with("\r\n\r\n#main-title {\n    color: green;\n}"));
+    }
+
+    fn get(dir: &StaticDir, target: &str) -> String {
+        get_with(dir, target, "")
+    }
+
+    fn get_with(dir: &StaticDir, target: &str, headers: &str) -> String {
+        let raw = format!("GET {} HTTP/1.1\r\n{}\r\n", target, headers);
+        let mut buf = std::io::BufReader::new(raw.as_bytes());
+        let req = Req::new(&mut buf).unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
+        dir.serve(&req, &mut res).unwrap();
+        String::from_utf8(write_buf).unwrap()
+    }
+
+    #[test]
+    fn resolves_under_prefix() {
+        let dir = StaticDir::new("/static/", "./test_data", StaticOptions::new()).unwrap();
+        assert!(dir.matches("/static"));
+        assert!(dir.matches("/static/assets/main.css?v=2"));
+        assert!(!dir.matches("/staticfile"));
+        assert_eq!(dir.resolve("/static/assets/main%2Ecss?v=2"), Some(path::Path::new("./test_data/assets/main.css").to_path_buf()));
+        assert_eq!(dir.resolve("/static/assets/../../secret"), None);
+        assert!(StaticDir::new("/", "./test_data/missing", StaticOptions::new()).is_err());
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        let n = |p: &str| normalize_path(p).map(|s| s.join("/"));
+        assert_eq!(n("//assets/./main.css"), Some(String::from("assets/main.css")));
+        assert_eq!(n("/a/b/../c/"), Some(String::from("a/c")));
+        assert_eq!(n("/%61ssets/main%20file.css"), Some(String::from("assets/main file.css")));
+        assert_eq!(n("/"), Some(String::new()));
+        assert_eq!(n("/.."), None);
+        assert_eq!(n("/a/%2e%2e/%2E%2E/b"), None);
+        assert_eq!(n("/a%2fb"), None);
+        assert_eq!(n("/a%00"), None);
+        assert_eq!(n("/%zz"), None);
+    }
+
+    #[test]
+    fn blocks_traversal_payloads() {
+        let dir = StaticDir::new("/", "./test_data", StaticOptions::new()).unwrap();
+        let payloads = [
+            "/../Cargo.toml",
+            "/assets/../../Cargo.toml",
+            "/%2e%2e/Cargo.toml",
+            "/%2E%2E/%2E%2E/etc/passwd",
+            "/..%2fCargo.toml",
+            "/%2e%2e%2fCargo.toml",
+            "/assets/..%2f..%2fCargo.toml",
+            "/..%5cCargo.toml",
+            "/..\\Cargo.toml",
+            "/assets/main.css%00.html",
+            "/%00",
+            "/%252e%252e/Cargo.toml",
+            "/%c0%ae%c0%ae/Cargo.toml",
+            "/./../Cargo.toml",
+            "//../Cargo.toml",
+            "/assets/%2e%2e/%2e%2e/Cargo.toml?x=1",
+            "/.git/config",
+            "/assets/%2egit",
+        ];
+        for payload in payloads.iter() {
+            assert!(get(&dir, payload).starts_with("HTTP/1.x 404 Not Found\r\n"), "{} was served", payload);
+        }
+        for ok in ["/assets/main.css", "//assets//main.css", "/assets/./main.css", "/assets/../assets/main.css", "/%61ssets/main.css"].iter() {
+            assert!(get(&dir, ok).starts_with("HTTP/1.x 200 OK\r\n"), "{} was not served", ok);
+        }
+    }
+
+    #[test]
+    fn sets_content_type() {
+        let types = MimeTypes::new();
+        let options = StaticOptions::new().nosniff(true).or_mime_types(&types);
+        let dir = StaticDir::new("/", "./test_data", options).unwrap();
+        let res = get(&dir, "/assets/main.css");
+        assert!(res.contains("Content-Type: text/css; charset=utf-8\r\n"));
+        assert!(res.contains("X-Content-Type-Options: nosniff\r\n"));
+        types.insert("css", "text/x-custom");
+        assert!(get(&dir, "/assets/main.css").contains("Content-Type: text/x-custom; charset=utf-8\r\n"));
+        assert!(get(&dir, "/aaa").contains("Content-Type: application/octet-stream\r\n"));
+    }
+
+    fn header<'r>(res: &'r str, name: &str) -> Option<&'r str> {
+        res.split("\r\n").find_map(|l| l.strip_prefix(name).and_then(|v| v.strip_prefix(": ")))
+    }
+
+    #[test]
+    fn answers_conditional_requests() {
+        for strategy in [EtagStrategy::Metadata, EtagStrategy::ContentHash].iter() {
+            let dir = StaticDir::new("/", "./test_data", StaticOptions::new().etag(*strategy)).unwrap();
+            let res = get(&dir, "/assets/main.css");
+            let etag = header(&res, "ETag").unwrap().to_string();
+            let modified = header(&res, "Last-Modified").unwrap().to_string();
+            assert_eq!(etag.starts_with("W/"), *strategy == EtagStrategy::Metadata);
+
+            let res = get_with(&dir, "/assets/main.css", &format!("If-None-Match: {}\r\n", etag));
+            assert!(res.starts_with("HTTP/1.x 304 Not Modified\r\n"));
+            assert_eq!(header(&res, "ETag"), Some(etag.as_str()));
+            assert!(res.ends_with("\r\n\r\n"));
+            let res = get_with(&dir, "/assets/main.css", &format!("If-Modified-Since: {}\r\n", modified));
+            assert!(res.starts_with("HTTP/1.x 304 Not Modified\r\n"));
+            let res = get_with(&dir, "/assets/main.css", "If-Match: \"other\"\r\n");
+            assert!(res.starts_with("HTTP/1.x 412 Precondition Failed\r\n"));
+            let res = get_with(&dir, "/assets/main.css", "If-None-Match: \"other\"\r\n");
+            assert!(res.starts_with("HTTP/1.x 200 OK\r\n"));
+        }
+        let dir = StaticDir::new("/", "./test_data", StaticOptions::new().etag(EtagStrategy::Disabled)).unwrap();
+        assert_eq!(header(&get(&dir, "/assets/main.css"), "ETag"), None);
+    }
+
+    #[test]
+    fn applies_cache_rules() {
+        let options = StaticOptions::new()
+            .cache("/index.html", CacheControl::no_cache())
+            .cache_rule(CacheRule::extension("css", CacheControl::immutable_asset()))
+            .vary("Accept-Language");
+        let dir = StaticDir::new("/", "./test_data", options.clone()).unwrap();
+        let res = get(&dir, "/assets/main.css");
+        assert_eq!(header(&res, "Cache-Control"), Some("public, max-age=31536000, immutable"));
+        assert_eq!(header(&res, "Vary"), Some("Accept-Language"));
+        let expires = header(&res, "Expires").and_then(date::parse_http_date).unwrap();
+        assert!(expires > time::SystemTime::now() + time::Duration::from_secs(364 * 24 * 60 * 60));
+
+        let etag = header(&res, "ETag").unwrap().to_string();
+        let res = get_with(&dir, "/assets/main.css", &format!("If-None-Match: {}\r\n", etag));
+        assert!(res.starts_with("HTTP/1.x 304 Not Modified\r\n"));
+        assert!(header(&res, "Cache-Control").is_some());
+        assert!(header(&res, "Vary").is_some());
+
+        let res = get(&dir, "/index.html");
+        assert_eq!(header(&res, "Cache-Control"), Some("no-cache"));
+        assert_eq!(header(&res, "Expires"), Some("Thu, 01 Jan 1970 00:00:00 GMT"));
+
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/static"), path::Path::new("./test_data"), &mut t, &options).unwrap();
+        let mut buf = std::io::BufReader::new("GET /static/assets/main.css HTTP/1.1\r\n\r\n".as_bytes());
+        let mut req = Req::new(&mut buf).unwrap();
+        let mut write_buf: Vec<u8> = vec![];
+        t.get("/static/assets/main.css", &Method::GET).unwrap().handle(&mut req, &mut Res::new(&mut write_buf));
+        assert_eq!(header(&String::from_utf8(write_buf).unwrap(), "Cache-Control"), Some("public, max-age=31536000, immutable"));
+    }
+
+    #[test]
+    fn serves_ranges() {
+        let options = StaticOptions::new().etag(EtagStrategy::ContentHash);
+        let dir = StaticDir::new("/", "./test_data", options.clone()).unwrap();
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/"), path::Path::new("./test_data"), &mut t, &options).unwrap();
+        let preloaded = |headers: &str| {
+            let raw = format!("GET /assets/main.css HTTP/1.1\r\n{}\r\n", headers);
+            let mut buf = std::io::BufReader::new(raw.as_bytes());
+            let mut req = Req::new(&mut buf).unwrap();
+            let mut write_buf: Vec<u8> = vec![];
+            let mut res = Res::new(&mut write_buf);
+            res.set_auto_date(false);
+            t.get("/assets/main.css", &Method::GET).unwrap().handle(&mut req, &mut res);
+            String::from_utf8(write_buf).unwrap()
+        };
+        let from_disk = |headers: &str| get_with(&dir, "/assets/main.css", headers);
+
+        for get in [&from_disk as &dyn Fn(&str) -> String, &preloaded].iter() {
+            let full = get("");
+            assert_eq!(header(&full, "Accept-Ranges"), Some("bytes"));
+            let etag = header(&full, "ETag").unwrap().to_string();
+
+            let res = get("Range: bytes=1-4\r\n");
+            assert!(res.starts_with("HTTP/1.x 206 Partial Content\r\n"));
+            assert_eq!(header(&res, "Content-Range"), Some("bytes 1-4/33"));
+            assert_eq!(header(&res, "Content-Length"), Some("4"));
+            assert!(res.ends_with("\r\n\r\nmain"));
+            assert!(get("Range: bytes=-2\r\n").ends_with("\r\n\r\n\n}"));
+
+            let res = get("Range: bytes=0-0,6-10\r\n");
+            assert!(res.starts_with("HTTP/1.x 206 Partial Content\r\n"));
+            let content_type = header(&res, "Content-Type").unwrap();
+            let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+            let body = &res[res.find("\r\n\r\n").unwrap() + 4..];
+            assert_eq!(header(&res, "Content-Length"), Some(body.len().to_string().as_str()));
+            assert_eq!(body, format!(
+                "--{b}\r\nContent-Type: text/css; charset=utf-8\r\nContent-Range: bytes 0-0/33\r\n\r\n#\r\n--{b}\r\nContent-Type: text/css; charset=utf-8\r\nContent-Range: bytes 6-10/33\r\n\r\ntitle\r\n--{b}--\r\n",
+                b = boundary,
+            ));
+
+            let res = get("Range: bytes=40-\r\n");
+            assert!(res.starts_with("HTTP/1.x 416 Range Not Satisfiable\r\n"));
+            assert_eq!(header(&res, "Content-Range"), Some("bytes */33"));
+            assert!(res.ends_with("\r\n\r\n"));
+
+            assert!(get(&format!("Range: bytes=1-4\r\nIf-Range: {}\r\n", etag)).starts_with("HTTP/1.x 206 Partial Content\r\n"));
+            let res = get("Range: bytes=1-4\r\nIf-Range: \"stale\"\r\n");
+            assert!(res.starts_with("HTTP/1.x 200 OK\r\n"));
+            assert!(res.ends_with("color: green;\n}"));
+            assert!(get("Range: lines=1-4\r\n").starts_with("HTTP/1.x 200 OK\r\n"));
+        }
+    }
+
+    fn trie_get(t: &Trie, target: &str, headers: &str) -> Option<String> {
+        let raw = format!("GET {} HTTP/1.1\r\n{}\r\n", target, headers);
+        let mut buf = std::io::BufReader::new(raw.as_bytes());
+        let mut req = Req::new(&mut buf).unwrap();
+        let mut handler = t.get(req.path(), &Method::GET)?;
+        let mut write_buf: Vec<u8> = vec![];
+        let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
+        handler.handle(&mut req, &mut res);
+        Some(String::from_utf8(write_buf).unwrap())
+    }
+
+    #[test]
+    fn serves_directories() {
+        let dir = StaticDir::new("/static", "./test_data", StaticOptions::new()).unwrap();
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/static"), path::Path::new("./test_data"), &mut t, &StaticOptions::new().preload(true)).unwrap();
+        let from_trie = |target: &str| trie_get(&t, target, "").unwrap_or_else(|| String::from("HTTP/1.x 404 Not Found\r\n"));
+        let from_disk = |target: &str| get(&dir, target);
+        for get in [&from_disk as &dyn Fn(&str) -> String, &from_trie].iter() {
+            let res = get("/static/");
+            assert!(res.starts_with("HTTP/1.x 200 OK\r\n"));
+            assert!(res.contains("Content-Type: text/html; charset=utf-8\r\n"));
+            assert!(res.contains("<title>my site</title>"));
+            let res = get("/static");
+            assert!(res.starts_with("HTTP/1.x 301 Moved Permanently\r\n"));
+            assert_eq!(header(&res, "Location"), Some("/static/"));
+            assert_eq!(header(&get("/static/assets"), "Location"), Some("/static/assets/"));
+            assert!(get("/static/assets/").starts_with("HTTP/1.x 404 Not Found\r\n"));
+        }
+        assert_eq!(header(&get(&dir, "/static/assets?v=1"), "Location"), Some("/static/assets/?v=1"));
+
+        // the target's own spelling must not make `Location` point at another host
+        let dir = StaticDir::new("/", "./test_data", StaticOptions::new()).unwrap();
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/"), path::Path::new("./test_data"), &mut t, &StaticOptions::new().preload(true)).unwrap();
+        for target in ["//evil.com/../assets", "//assets", "/./assets", "//evil.com/../assets?q=1"].iter() {
+            let res = get(&dir, target);
+            assert!(res.starts_with("HTTP/1.x 301 Moved Permanently\r\n"), "{}: {}", target, res);
+            assert!(header(&res, "Location").unwrap().starts_with("/assets/"), "{}: {}", target, res);
+            if let Some(res) = trie_get(&t, target, "") {
+                let location = header(&res, "Location").unwrap_or("/assets/");
+                assert!(location.starts_with("/assets/"), "{}: {}", target, res);
+            }
+        }
+        assert_eq!(header(&get(&dir, "//evil.com/../assets?q=1"), "Location"), Some("/assets/?q=1"));
+        assert_eq!(header(&trie_get(&t, "/assets", "").unwrap(), "Location"), Some("/assets/"));
+
+        let options = StaticOptions::new().index(None).autoindex(true).vary("Accept-Encoding");
+        let dir = StaticDir::new("/", "./test_data", options.clone()).unwrap();
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/"), path::Path::new("./test_data"), &mut t, &options).unwrap();
+        let from_trie = |target: &str, headers: &str| trie_get(&t, target, headers).unwrap();
+        let from_disk = |target: &str, headers: &str| get_with(&dir, target, headers);
+        for get in [&from_disk as &dyn Fn(&str, &str) -> String, &from_trie].iter() {
+            let res = get("/", "");
+            assert!(res.contains("Content-Type: text/html; charset=utf-8\r\n"));
+            assert_eq!(header(&res, "Vary"), Some("Accept-Encoding, Accept"));
+            assert!(res.contains("<h1>Index of /</h1>"));
+            assert!(res.contains("<a href=\"./assets/\">assets/</a>"));
+            assert!(res.contains("<a href=\"./index.html\">index.html</a></td><td>"));
+            assert!(!res.contains("../"));
+            let res = get("/assets/", "Accept: application/json\r\n");
+            assert!(res.contains("Content-Type: application/json\r\n"));
+            assert!(res.contains("\r\n\r\n[{\"name\":\"main.css\",\"type\":\"file\",\"size\":33,\"modified\":\""));
+            assert!(get("/assets/", "").contains("<a href=\"../\">../</a>"));
+        }
+    }
+
+    #[test]
+    fn serves_precompressed_siblings() {
+        let root = std::env::temp_dir().join(format!("hermitcrab-precompressed-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("app.js"), "plain javascript").unwrap();
+        fs::write(root.join("app.js.gz"), "gzipped").unwrap();
+        fs::write(root.join("app.js.br"), "brotli").unwrap();
+        fs::write(root.join("other.js"), "other").unwrap();
+
+        let options = StaticOptions::new().etag(EtagStrategy::ContentHash);
+        let dir = StaticDir::new("/", &root, options.clone()).unwrap();
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/"), &root, &mut t, &options.clone().preload(true)).unwrap();
+        let from_disk = |target: &str, headers: &str| get_with(&dir, target, headers);
+        let from_trie = |target: &str, headers: &str| trie_get(&t, target, headers).unwrap();
+        for get in [&from_disk as &dyn Fn(&str, &str) -> String, &from_trie].iter() {
+            let res = get("/app.js", "Accept-Encoding: gzip, deflate, br\r\n");
+            assert_eq!(header(&res, "Content-Encoding"), Some("br"));
+            assert_eq!(header(&res, "Content-Type"), Some("text/javascript; charset=utf-8"));
+            assert_eq!(header(&res, "Vary"), Some("Accept-Encoding"));
+            assert!(res.ends_with("\r\n\r\nbrotli"));
+            let br_etag = header(&res, "ETag").unwrap().to_string();
+            assert!(br_etag.ends_with("-br\""));
+
+            let res = get("/app.js", "Accept-Encoding: gzip\r\n");
+            assert_eq!(header(&res, "Content-Encoding"), Some("gzip"));
+            assert!(res.ends_with("\r\n\r\ngzipped"));
+            let gzip_etag = header(&res, "ETag").unwrap().to_string();
+
+            let res = get("/app.js", "");
+            assert_eq!(header(&res, "Content-Encoding"), None);
+            assert_eq!(header(&res, "Vary"), Some("Accept-Encoding"));
+            assert!(res.ends_with("\r\n\r\nplain javascript"));
+            let plain_etag = header(&res, "ETag").unwrap().to_string();
+            assert!(plain_etag != br_etag && plain_etag != gzip_etag && br_etag != gzip_etag);
+
+            let res = get("/app.js", &format!("Accept-Encoding: br\r\nIf-None-Match: {}\r\n", br_etag));
+            assert!(res.starts_with("HTTP/1.x 304 Not Modified\r\n"));
+            assert_eq!(header(&res, "Vary"), Some("Accept-Encoding"));
+            assert!(get("/app.js", &format!("Accept-Encoding: gzip\r\nIf-None-Match: {}\r\n", br_etag)).starts_with("HTTP/1.x 200 OK\r\n"));
+
+            let res = get("/app.js", &format!("Accept-Encoding: br\r\nRange: bytes=1-3\r\nIf-Range: {}\r\n", br_etag));
+            assert_eq!(header(&res, "Content-Range"), Some("bytes 1-3/6"));
+            assert!(res.ends_with("\r\n\r\nrot"));
+            let res = get("/app.js", &format!("Accept-Encoding: gzip\r\nRange: bytes=1-3\r\nIf-Range: {}\r\n", br_etag));
+            assert!(res.starts_with("HTTP/1.x 200 OK\r\n"));
+
+            let res = get("/other.js", "Accept-Encoding: br\r\n");
+            assert_eq!(header(&res, "Content-Encoding"), None);
+            assert_eq!(header(&res, "Vary"), None);
+        }
+        let dir = StaticDir::new("/", &root, StaticOptions::new().precompressed(&[ContentEncoding::Gzip])).unwrap();
+        assert_eq!(header(&get_with(&dir, "/app.js", "Accept-Encoding: br, gzip\r\n"), "Content-Encoding"), Some("gzip"));
+        let dir = StaticDir::new("/", &root, StaticOptions::new().precompressed(&[])).unwrap();
+        assert!(get_with(&dir, "/app.js", "Accept-Encoding: br, gzip\r\n").ends_with("plain javascript"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_symlink_policy() {
+        use std::os::unix::fs::symlink;
+        let base = std::env::temp_dir().join(format!("hermitcrab-symlink-{}", std::process::id()));
+        let root = base.join("root");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(base.join("outside.txt"), "outside").unwrap();
+        fs::write(root.join("inside.txt"), "inside").unwrap();
+        fs::write(root.join(".hidden"), "hidden").unwrap();
+        symlink(base.join("outside.txt"), root.join("out-link.txt")).unwrap();
+        symlink(root.join("inside.txt"), root.join("in-link.txt")).unwrap();
+        fs::create_dir_all(base.join("outside-dir")).unwrap();
+        fs::write(base.join("outside-dir/secret.txt"), "secret").unwrap();
+        symlink(base.join("outside-dir"), root.join("out-dir")).unwrap();
+
+        let from_disk = |options: StaticOptions, target: &str| {
+            let dir = StaticDir::new("/", &root, options).unwrap();
+            get(&dir, target).starts_with("HTTP/1.x 200 OK\r\n")
+        };
+        let preloaded = |options: StaticOptions, target: &str| {
+            let mut t = Trie::default();
+            add_directory_to_trie(path::Path::new("/"), &root, &mut t, &options.preload(true)).unwrap();
+            trie_get(&t, target, "").map(|res| res.starts_with("HTTP/1.x 200 OK\r\n")).unwrap_or(false)
+        };
+        for served in [&from_disk as &dyn Fn(StaticOptions, &str) -> bool, &preloaded].iter() {
+            assert!(served(StaticOptions::new(), "/in-link.txt"));
+            assert!(!served(StaticOptions::new(), "/out-link.txt"));
+            assert!(!served(StaticOptions::new(), "/out-dir/secret.txt"));
+            assert!(served(StaticOptions::new().symlinks(SymlinkPolicy::Follow), "/out-link.txt"));
+            assert!(served(StaticOptions::new().symlinks(SymlinkPolicy::Follow), "/out-dir/secret.txt"));
+            assert!(!served(StaticOptions::new().symlinks(SymlinkPolicy::Deny), "/in-link.txt"));
+            assert!(served(StaticOptions::new().symlinks(SymlinkPolicy::Deny), "/inside.txt"));
+            assert!(!served(StaticOptions::new(), "/.hidden"));
+            assert!(served(StaticOptions::new().allow_hidden(true), "/.hidden"));
+        }
+        let options = StaticOptions::new().autoindex(true).index(None);
+        let mut t = Trie::default();
+        add_directory_to_trie(path::Path::new("/"), &root, &mut t, &options.clone().preload(true)).unwrap();
+        for listing in [get(&StaticDir::new("/", &root, options).unwrap(), "/"), trie_get(&t, "/", "").unwrap()].iter() {
+            assert!(listing.contains(">in-link.txt<"));
+            assert!(!listing.contains("out-link.txt"));
+            assert!(!listing.contains("out-dir"));
+            assert!(!listing.contains(".hidden"));
+        }
+        fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn picks_up_disk_changes() {
+        let root = std::env::temp_dir().join(format!("hermitcrab-static-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        let dir = StaticDir::new("/", &root, StaticOptions::new()).unwrap();
+
+        assert!(get(&dir, "/late.txt").starts_with("HTTP/1.x 404 Not Found\r\n"));
+        fs::write(root.join("late.txt"), "first").unwrap();
+        let res = get(&dir, "/late.txt");
+        assert!(res.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(res.ends_with("\r\n\r\nfirst"));
+        fs::write(root.join("late.txt"), "second").unwrap();
+        assert!(get(&dir, "/late.txt").ends_with("\r\n\r\nsecond"));
+        fs::remove_file(root.join("late.txt")).unwrap();
+        assert!(get(&dir, "/late.txt").starts_with("HTTP/1.x 404 Not Found\r\n"));
+        fs::remove_dir_all(&root).unwrap();
     }
 }
\ No newline at end of file
@@ -0,0 +1,226 @@
+use std::fmt;
+
+/// An HTTP status code, always in `100..=999`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct StatusCode(u16);
+
+impl StatusCode {
+    pub const CONTINUE: StatusCode = StatusCode(100);
+    pub const SWITCHING_PROTOCOLS: StatusCode = StatusCode(101);
+    pub const PROCESSING: StatusCode = StatusCode(102);
+    pub const EARLY_HINTS: StatusCode = StatusCode(103);
+    pub const OK: StatusCode = StatusCode(200);
+    pub const CREATED: StatusCode = StatusCode(201);
+    pub const ACCEPTED: StatusCode = StatusCode(202);
+    pub const NON_AUTHORITATIVE_INFORMATION: StatusCode = StatusCode(203);
+    pub const NO_CONTENT: StatusCode = StatusCode(204);
+    pub const RESET_CONTENT: StatusCode = StatusCode(205);
+    pub const PARTIAL_CONTENT: StatusCode = StatusCode(206);
+    pub const MULTI_STATUS: StatusCode = StatusCode(207);
+    pub const ALREADY_REPORTED: StatusCode = StatusCode(208);
+    pub const IM_USED: StatusCode = StatusCode(226);
+    pub const MULTIPLE_CHOICES: StatusCode = StatusCode(300);
+    pub const MOVED_PERMANENTLY: StatusCode = StatusCode(301);
+    pub const FOUND: StatusCode = StatusCode(302);
+    pub const SEE_OTHER: StatusCode = StatusCode(303);
+    pub const NOT_MODIFIED: StatusCode = StatusCode(304);
+    pub const USE_PROXY: StatusCode = StatusCode(305);
+    pub const TEMPORARY_REDIRECT: StatusCode = StatusCode(307);
+    pub const PERMANENT_REDIRECT: StatusCode = StatusCode(308);
+    pub const BAD_REQUEST: StatusCode = StatusCode(400);
+    pub const UNAUTHORIZED: StatusCode = StatusCode(401);
+    pub const PAYMENT_REQUIRED: StatusCode = StatusCode(402);
+    pub const FORBIDDEN: StatusCode = StatusCode(403);
+    pub const NOT_FOUND: StatusCode = StatusCode(404);
+    pub const METHOD_NOT_ALLOWED: StatusCode = StatusCode(405);
+    pub const NOT_ACCEPTABLE: StatusCode = StatusCode(406);
+    pub const PROXY_AUTHENTICATION_REQUIRED: StatusCode = StatusCode(407);
+    pub const REQUEST_TIMEOUT: StatusCode = StatusCode(408);
+    pub const CONFLICT: StatusCode = StatusCode(409);
+    pub const GONE: StatusCode = StatusCode(410);
+    pub const LENGTH_REQUIRED: StatusCode = StatusCode(411);
+    pub const PRECONDITION_FAILED: StatusCode = StatusCode(412);
+    pub const PAYLOAD_TOO_LARGE: StatusCode = StatusCode(413);
+    pub const URI_TOO_LONG: StatusCode = StatusCode(414);
+    pub const UNSUPPORTED_MEDIA_TYPE: StatusCode = StatusCode(415);
+    pub const RANGE_NOT_SATISFIABLE: StatusCode = StatusCode(416);
+    pub const EXPECTATION_FAILED: StatusCode = StatusCode(417);
+    pub const IM_A_TEAPOT: StatusCode = StatusCode(418);
+    pub const MISDIRECTED_REQUEST: StatusCode = StatusCode(421);
+    pub const UNPROCESSABLE_ENTITY: StatusCode = StatusCode(422);
+    pub const LOCKED: StatusCode = StatusCode(423);
+    pub const FAILED_DEPENDENCY: StatusCode = StatusCode(424);
+    pub const TOO_EARLY: StatusCode = StatusCode(425);
+    pub const UPGRADE_REQUIRED: StatusCode = StatusCode(426);
+    pub const PRECONDITION_REQUIRED: StatusCode = StatusCode(428);
+    pub const TOO_MANY_REQUESTS: StatusCode = StatusCode(429);
+    pub const REQUEST_HEADER_FIELDS_TOO_LARGE: StatusCode = StatusCode(431);
+    pub const UNAVAILABLE_FOR_LEGAL_REASONS: StatusCode = StatusCode(451);
+    pub const INTERNAL_SERVER_ERROR: StatusCode = StatusCode(500);
+    pub const NOT_IMPLEMENTED: StatusCode = StatusCode(501);
+    pub const BAD_GATEWAY: StatusCode = StatusCode(502);
+    pub const SERVICE_UNAVAILABLE: StatusCode = StatusCode(503);
+    pub const GATEWAY_TIMEOUT: StatusCode = StatusCode(504);
+    pub const HTTP_VERSION_NOT_SUPPORTED: StatusCode = StatusCode(505);
+    pub const VARIANT_ALSO_NEGOTIATES: StatusCode = StatusCode(506);
+    pub const INSUFFICIENT_STORAGE: StatusCode = StatusCode(507);
+    pub const LOOP_DETECTED: StatusCode = StatusCode(508);
+    pub const NOT_EXTENDED: StatusCode = StatusCode(510);
+    pub const NETWORK_AUTHENTICATION_REQUIRED: StatusCode = StatusCode(511);
+
+    /// `None` unless `code` is a three digit number.
+    pub fn from_u16(code: u16) -> Option<Self> {
+        if (100..1000).contains(&code) {
+            Some(StatusCode(code))
+        } else {
+            None
+        }
+    }
+
+    pub fn as_u16(self) -> u16 {
+        self.0
+    }
+
+    /// The reason phrase registered for this code, if it is a registered one.
+    pub fn canonical_reason(self) -> Option<&'static str> {
+        match self.0 {
+            100 => Some("Continue"),
+            101 => Some("Switching Protocols"),
+            102 => Some("Processing"),
+            103 => Some("Early Hints"),
+            200 => Some("OK"),
+            201 => Some("Created"),
+            202 => Some("Accepted"),
+            203 => Some("Non-Authoritative Information"),
+            204 => Some("No Content"),
+            205 => Some("Reset Content"),
+            206 => Some("Partial Content"),
+            207 => Some("Multi-Status"),
+            208 => Some("Already Reported"),
+            226 => Some("IM Used"),
+            300 => Some("Multiple Choices"),
+            301 => Some("Moved Permanently"),
+            302 => Some("Found"),
+            303 => Some("See Other"),
+            304 => Some("Not Modified"),
+            305 => Some("Use Proxy"),
+            307 => Some("Temporary Redirect"),
+            308 => Some("Permanent Redirect"),
+            400 => Some("Bad Request"),
+            401 => Some("Unauthorized"),
+            402 => Some("Payment Required"),
+            403 => Some("Forbidden"),
+            404 => Some("Not Found"),
+            405 => Some("Method Not Allowed"),
+            406 => Some("Not Acceptable"),
+            407 => Some("Proxy Authentication Required"),
+            408 => Some("Request Timeout"),
+            409 => Some("Conflict"),
+            410 => Some("Gone"),
+            411 => Some("Length Required"),
+            412 => Some("Precondition Failed"),
+            413 => Some("Payload Too Large"),
+            414 => Some("URI Too Long"),
+            415 => Some("Unsupported Media Type"),
+            416 => Some("Range Not Satisfiable"),
+            417 => Some("Expectation Failed"),
+            418 => Some("I'm a teapot"),
+            421 => Some("Misdirected Request"),
+            422 => Some("Unprocessable Entity"),
+            423 => Some("Locked"),
+            424 => Some("Failed Dependency"),
+            425 => Some("Too Early"),
+            426 => Some("Upgrade Required"),
+            428 => Some("Precondition Required"),
+            429 => Some("Too Many Requests"),
+            431 => Some("Request Header Fields Too Large"),
+            451 => Some("Unavailable For Legal Reasons"),
+            500 => Some("Internal Server Error"),
+            501 => Some("Not Implemented"),
+            502 => Some("Bad Gateway"),
+            503 => Some("Service Unavailable"),
+            504 => Some("Gateway Timeout"),
+            505 => Some("HTTP Version Not Supported"),
+            506 => Some("Variant Also Negotiates"),
+            507 => Some("Insufficient Storage"),
+            508 => Some("Loop Detected"),
+            510 => Some("Not Extended"),
+            511 => Some("Network Authentication Required"),
+            _ => None,
+        }
+    }
+
+    pub fn is_informational(self) -> bool {
+        self.0 < 200
+    }
+
+    pub fn is_success(self) -> bool {
+        (200..300).contains(&self.0)
+    }
+
+    pub fn is_redirection(self) -> bool {
+        (300..400).contains(&self.0)
+    }
+
+    pub fn is_client_error(self) -> bool {
+        (400..500).contains(&self.0)
+    }
+
+    pub fn is_server_error(self) -> bool {
+        (500..600).contains(&self.0)
+    }
+}
+
+impl Default for StatusCode {
+    fn default() -> Self {
+        StatusCode::OK
+    }
+}
+
+impl From<StatusCode> for u16 {
+    fn from(code: StatusCode) -> u16 {
+        code.0
+    }
+}
+
+impl PartialEq<u16> for StatusCode {
+    fn eq(&self, other: &u16) -> bool {
+        self.0 == *other
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.canonical_reason() {
+            Some(reason) => write!(f, "{} {}", self.0, reason),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_reasons() {
+        assert_eq!(StatusCode::NOT_FOUND.canonical_reason(), Some("Not Found"));
+        assert_eq!(StatusCode::from_u16(431).and_then(StatusCode::canonical_reason), Some("Request Header Fields Too Large"));
+        assert_eq!(StatusCode::from_u16(599).unwrap().canonical_reason(), None);
+        assert_eq!(StatusCode::from_u16(99), None);
+        assert_eq!(StatusCode::from_u16(1000), None);
+        assert_eq!(StatusCode::IM_A_TEAPOT.to_string(), "418 I'm a teapot");
+        assert_eq!(StatusCode::from_u16(299).unwrap().to_string(), "299");
+    }
+
+    #[test]
+    fn classes() {
+        assert!(StatusCode::CONTINUE.is_informational());
+        assert!(StatusCode::NO_CONTENT.is_success());
+        assert!(StatusCode::SEE_OTHER.is_redirection());
+        assert!(StatusCode::GONE.is_client_error());
+        assert!(StatusCode::BAD_GATEWAY.is_server_error());
+        assert_eq!(u16::from(StatusCode::OK), 200);
+        assert_eq!(StatusCode::CREATED, 201);
+    }
+}
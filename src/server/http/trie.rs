@@ -33,9 +33,11 @@ impl Node {
             Node::Passby(word, children) => {
                 Self::get_passby(word, children, path, p_begin, method)
             },
-            Node::Terminal(m, handler) => {
+            // a terminal only matches once the whole path is consumed
+            Node::Terminal(m, handler) if p_begin == path.len() => {
                 Self::get_terminal(method, m, handler)
-            }
+            },
+            Node::Terminal(..) => None,
         }
     }
 
@@ -79,41 +81,27 @@ impl Node {
 
     #[inline]
     fn attach_passby(v: &mut Vec<Box<Self>>, p: &str, p_begin: usize, m: &Method, handler: &HandlerRef) {
-        let num_nodes = v.len();
         for (idx, child) in v.iter_mut().enumerate() {
             if let Node::Passby(word, grandchildren) = &mut **child {
                 let mut remain_chars = p[p_begin..].chars();
                 let shared_length = Self::iter_over_shared_chars(&mut word.chars(), &mut remain_chars);
-                let new_p_begin = p_begin + shared_length;
                 if shared_length != 0 {
-                    if new_p_begin == p.len() { // this is weird, cuz checked in the parent function
-                        child.attach(p, new_p_begin, m, handler);
-                        return;
-                    }
-                    // split path; move grandchildren to new passby; attach passby to current child
-                    let new_passby = Self::new_passby_and_attach(p, new_p_begin, m, handler);
-                    let split_passby = Node::Passby(word[shared_length..].to_string(), grandchildren.clone());
-                    let mut new_children = vec![Box::new(new_passby)];
-                    if new_children[0].is_ahead_of(&word[shared_length..]) {
-                        new_children.insert(0, Box::new(split_passby));
-                    } else {
-                        new_children.push(Box::new(split_passby));
+                    if shared_length < word.len() {
+                        // split word; the latter half keeps the grandchildren
+                        let split_passby = Node::Passby(word[shared_length..].to_string(), std::mem::take(grandchildren));
+                        word.truncate(shared_length);
+                        grandchildren.push(Box::new(split_passby));
                     }
-                    let mut word_latter_half = word[..shared_length].to_string();
-                    std::mem::swap(word, &mut word_latter_half);
-                    std::mem::swap(grandchildren, &mut new_children);
+                    child.attach(p, p_begin + shared_length, m, handler);
                     return;
                 }
-                if child.is_ahead_of(&p[new_p_begin..]) {
-                    v.insert(idx, Box::new(Self::new_passby_and_attach(p, new_p_begin, m, handler)));
+                if child.is_ahead_of(&p[p_begin..]) {
+                    v.insert(idx, Box::new(Self::new_passby_and_attach(p, p_begin, m, handler)));
                     return;
                 }
-                if idx == num_nodes-1 {
-                    v.push(Box::new(Self::new_passby_and_attach(p, new_p_begin, m, handler)));
-                    return
-                }
             }
         }
+        v.push(Box::new(Self::new_passby_and_attach(p, p_begin, m, handler)));
     }
 
     fn new_passby_and_attach(p: &str, p_begin: usize, m: &Method, handler: &HandlerRef) -> Self {
@@ -122,6 +110,7 @@ impl Node {
         new_passby
     }
 
+    // the length in bytes of the common prefix
     fn iter_over_shared_chars(p1: &mut std::str::Chars, p2: &mut std::str::Chars) -> usize {
         let mut shared_length = 0usize;
         loop {
@@ -129,7 +118,7 @@ impl Node {
                 if c1 != c2 {
                     break;
                 }
-                shared_length += 1;
+                shared_length += c1.len_utf8();
             } else {
                 break;
             }
@@ -149,6 +138,10 @@ impl Node {
                     v.insert(idx, Box::new(new));
                     return;
                 }
+            } else {
+                // terminals go ahead of passbys, whose search stops at the first one past the path
+                v.insert(idx, Box::new(new));
+                return;
             }
         }
         v.push(Box::new(new));
@@ -230,6 +223,7 @@ mod tests {
 
         let mut write_buf: Vec<u8> = vec![];
         let mut res = Res::new(&mut write_buf);
+        res.set_auto_date(false);
 
         let mut retrieved_handler = root_node.get("what", 0, &Method::GET).unwrap();
         retrieved_handler.handle(&mut incoming_req, &mut res);
@@ -245,6 +239,30 @@ mod tests {
         assert!(h.is_some());
     }
 
+    #[test]
+    fn matches_whole_paths_only() {
+        let mut tr = Trie::default();
+        tr.insert("/assets/main.css", &Method::GET, &get_handler_ref(0));
+        tr.insert("/assets", &Method::GET, &get_handler_ref(1));
+        tr.insert("/assets/", &Method::GET, &get_handler_ref(2));
+        tr.insert("/assets/main.js", &Method::GET, &get_handler_ref(3));
+        tr.insert("/ässets", &Method::GET, &get_handler_ref(4));
+        tr.insert("/", &Method::GET, &get_handler_ref(5));
+        tr.insert("/zzz", &Method::POST, &get_handler_ref(6));
+        tr._print();
+        for (i, p) in ["/assets/main.css", "/assets", "/assets/", "/assets/main.js", "/ässets", "/"].iter().enumerate() {
+            let mut buf = std::io::BufReader::new("GET / HTTP/1.1\r\n\r\n".as_bytes());
+            let mut req = Req::new(&mut buf).unwrap();
+            let mut write_buf: Vec<u8> = vec![];
+            tr.get(p, &Method::GET).unwrap().handle(&mut req, &mut Res::new(&mut write_buf));
+            assert!(write_buf.ends_with(format!("sample handler #{}", i).as_bytes()), "{}", p);
+        }
+        for p in ["/assets/main.cssx", "/assets/main", "/asset", "/ä", "/zzz", "/assets/x"].iter() {
+            assert!(tr.get(p, &Method::GET).is_none(), "{}", p);
+        }
+        assert!(tr.get("/zzz", &Method::POST).is_some());
+    }
+
     #[test]
     fn node_return_none_when_nothing_found() {
         let n = get_node_with_whenwhere();
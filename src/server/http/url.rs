@@ -0,0 +1,74 @@
+/// Decodes `%XX` escapes, optionally treating `+` as a space as forms do.
+/// Returns `None` when an escape is truncated or not hexadecimal.
+pub fn percent_decode(input: &[u8], plus_as_space: bool) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'%' => {
+                let hi = input.get(i + 1).and_then(|c| hex_value(*c))?;
+                let lo = input.get(i + 2).and_then(|c| hex_value(*c))?;
+                out.push(hi << 4 | lo);
+                i += 3;
+            },
+            b'+' if plus_as_space => {
+                out.push(b' ');
+                i += 1;
+            },
+            c => {
+                out.push(c);
+                i += 1;
+            },
+        }
+    }
+    Some(out)
+}
+
+/// Escapes everything but unreserved characters (`A-Z a-z 0-9 - . _ ~`) as `%XX`,
+/// e.g. to put a file name into a path segment.
+pub fn percent_encode(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for b in input.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_escapes() {
+        assert_eq!(percent_decode(b"a%20b+c%2Fd", true).unwrap(), b"a b c/d");
+        assert_eq!(percent_decode(b"a+b", false).unwrap(), b"a+b");
+        assert_eq!(percent_decode(b"%e4%BD%A0", false).unwrap(), "你".as_bytes());
+    }
+
+    #[test]
+    fn encodes_segments() {
+        assert_eq!(percent_encode("a b/c?d%.txt"), "a%20b%2Fc%3Fd%25.txt");
+        assert_eq!(percent_encode("你"), "%E4%BD%A0");
+        assert_eq!(percent_decode(percent_encode("<a&b>").as_bytes(), false).unwrap(), b"<a&b>");
+    }
+
+    #[test]
+    fn rejects_bad_escapes() {
+        assert!(percent_decode(b"100%", false).is_none());
+        assert!(percent_decode(b"%4", false).is_none());
+        assert!(percent_decode(b"%zz", false).is_none());
+    }
+}
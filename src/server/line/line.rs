@@ -9,6 +9,7 @@ use std::{
 };
 
 use crate::logger::help::*;
+use super::super::http::ConnInfo;
 
 const LINE_STREAM_TIMEOUT_SECS: u64 = 10;
 const SYNC_CHANNEL_BUFFER_SIZE: usize = 2;
@@ -35,7 +36,7 @@ pub struct Line {
 }
 
 impl Line {
-    pub fn new(mut stream_handler: impl FnMut(net::TcpStream) -> io::Result<()> + Send + Sync + 'static) -> Self {
+    pub fn new(mut stream_handler: impl FnMut(net::TcpStream, ConnInfo) -> io::Result<()> + Send + Sync + 'static) -> Self {
         let (s, r) = mpsc::sync_channel::<Option<net::TcpStream>>(SYNC_CHANNEL_BUFFER_SIZE);
         thread::spawn(move || {
             for stream in r {
@@ -43,7 +44,9 @@ impl Line {
                     let t = Some(time::Duration::from_secs(LINE_STREAM_TIMEOUT_SECS));
                     st.set_read_timeout(t).unwrap();
                     st.set_write_timeout(t).unwrap();
-                    match stream_handler(st) {
+                    let conn = ConnInfo::from_stream(&st);
+                    trace!("connection #{} from {:?}", conn.id, conn.peer_addr);
+                    match stream_handler(st, conn) {
                         Ok(_) => {},
                         Err(e) => {
                             error!("line handler result error: {}", e);
@@ -97,7 +100,7 @@ mod tests {
         // handler closure and its captured values
         let buf = Arc::new(Mutex::new(vec![0u8]));
         let buf_ref = buf.clone();
-        let mut l = Line::new(move |mut stream: net::TcpStream| {
+        let mut l = Line::new(move |mut stream: net::TcpStream, _| {
             let mut buf_guard = buf_ref.lock().unwrap();
             buf_guard.clear();
 
@@ -126,7 +129,7 @@ mod tests {
     fn receive_busy_while_processing_stream() {
         let (server, server_port) = get_tcpserver_and_port().unwrap();
         
-        let mut l = Line::new(move |_| {
+        let mut l = Line::new(move |_, _| {
             thread::sleep(time::Duration::from_millis(600));
             Ok(())
         });
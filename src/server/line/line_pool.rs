@@ -9,6 +9,10 @@ pub struct LinePool {
     lines: Vec<Line>,
     max_line: usize,
     pub http_muxer: http::Muxer,
+    pub limits: http::Limits,
+    pub server_header: Option<String>,
+    pub proxy_protocol: Option<http::ProxyProtocol>,
+    pub trusted_proxies: Vec<http::Cidr>,
 }
 
 impl LinePool {
@@ -17,6 +21,10 @@ impl LinePool {
             lines: vec![],
             max_line,
             http_muxer: http::Muxer::default(),
+            limits: http::Limits::default(),
+            server_header: None,
+            proxy_protocol: None,
+            trusted_proxies: vec![],
         }
     }
 
@@ -35,22 +43,75 @@ impl LinePool {
         }
     }
 
-    fn get_muxer(&mut self) -> impl FnMut(net::TcpStream) -> io::Result<()> + Send + Sync + 'static {
+    fn get_muxer(&mut self) -> impl FnMut(net::TcpStream, http::ConnInfo) -> io::Result<()> + Send + Sync + 'static {
         let http_muxer = self.http_muxer.clone();
-        move |s: net::TcpStream| {
+        let limits = self.limits;
+        let server_header = self.server_header.clone();
+        let proxy_protocol = self.proxy_protocol.clone();
+        let trusted_proxies = self.trusted_proxies.clone();
+        // reused for the head of every request this line serves
+        let mut head_buf = Vec::with_capacity(4096);
+        move |s: net::TcpStream, mut conn: http::ConnInfo| {
             let mut buf_read = io::BufReader::new(&s);
-            let mut req = http::Req::new(&mut buf_read)?;
+            let mut interim_write = &s;
+            let from_trusted_proxy = match (&proxy_protocol, conn.peer_addr) {
+                (Some(pp), Some(peer)) => pp.is_trusted(peer.ip()),
+                _ => false,
+            };
+            if from_trusted_proxy {
+                match http::proxy_protocol::read_header(&mut buf_read) {
+                    Ok(header) => conn.proxied_addr = header.source,
+                    Err(e) => {
+                        warn!("dropping connection from {:?}: {}", conn.peer_addr, e);
+                        s.shutdown(net::Shutdown::Both)?;
+                        return Ok(());
+                    },
+                }
+            }
+            let mut req = match http::Req::with_buffer(&mut buf_read, &mut head_buf, &limits) {
+                Ok(req) => req,
+                Err(e) => {
+                    let peer = conn.peer_addr.map(|a| a.to_string()).unwrap_or_else(|| String::from("unknown peer"));
+                    if let Some(status) = e.status() {
+                        warn!("bad request from {}: {}", peer, e);
+                        let mut buf_write = io::BufWriter::new(&s);
+                        let mut res = http::Res::new(&mut buf_write);
+                        res.set_server(server_header.as_deref());
+                        res.set_status_code(status);
+                        if let Some(h) = http_muxer.parse_error_handler() {
+                            h(&e, &mut res);
+                        }
+                        if !res.responded() {
+                            res.respond(status.canonical_reason().unwrap_or("").as_bytes())?;
+                        }
+                        s.shutdown(net::Shutdown::Both)?;
+                    } else {
+                        debug!("dropping connection from {}: {}", peer, e);
+                    }
+                    return Ok(());
+                },
+            };
+            conn.request_count += 1;
+            req.set_conn_info(conn);
+            req.trust_proxies(&trusted_proxies);
+            req.set_interim_writer(&mut interim_write);
             info!("{} {}", req.method(), req.path());
             let mut buf_write = io::BufWriter::new(&s);
             let mut res = http::Res::new(&mut buf_write);
-            if let Some(mut handler) = http_muxer.get_handler(&mut req) {
+            res.set_server(server_header.as_deref());
+            res.set_raw_stream(&s);
+            // decided before the body is touched so clients waiting on `100 Continue` are answered right away
+            if req.has_unsupported_expectation() {
+                res.set_status_code(http::StatusCode::EXPECTATION_FAILED);
+                res.respond(b"Expectation Failed")?;
+            } else if let Some(mut handler) = http_muxer.get_handler(&mut req) {
                 handler.handle(&mut req, &mut res);
                 if !res.responded() {
                     res.set_status(500, "Empty Response");
                     res.respond(b"Empty Response")?;
                 }
             } else {
-                res.set_status(404, "Not Found");
+                res.set_status_code(http::StatusCode::NOT_FOUND);
                 res.respond(b"Not Found")?;
             }
             s.shutdown(net::Shutdown::Both)?;
@@ -86,4 +147,134 @@ impl LinePool {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            io::{Read, Write},
+            thread,
+        },
+    };
+
+    fn round_trip(pool: &mut LinePool, request: &[u8], wait_for_continue: bool) -> String {
+        let listener = net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (conn, _) = listener.accept().unwrap();
+        let mut handler = pool.get_muxer();
+        let info = http::ConnInfo::from_stream(&conn);
+        let server = thread::spawn(move || handler(conn, info));
+
+        let head_end = request.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        client.write_all(&request[..head_end]).unwrap();
+        let mut received = vec![];
+        if wait_for_continue {
+            let mut interim = [0u8; 25];
+            client.read_exact(&mut interim).unwrap();
+            received.extend_from_slice(&interim);
+        }
+        client.write_all(&request[head_end..]).unwrap();
+        client.read_to_end(&mut received).unwrap();
+        server.join().unwrap().unwrap();
+        String::from_utf8(received).unwrap()
+    }
+
+    fn echo_pool() -> LinePool {
+        let mut pool = LinePool::new(1);
+        pool.limits.max_body_size = Some(8);
+        pool.http_muxer.add_handler(http::Method::POST, "/echo", |req: &mut http::Req, res: &mut http::Res| {
+            let mut body = vec![];
+            req.body().read_to_end(&mut body).unwrap();
+            res.respond(&body).unwrap();
+        });
+        pool
+    }
+
+    #[test]
+    fn sends_continue_when_body_is_read() {
+        let res = round_trip(&mut echo_pool(), b"POST /echo HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\nHello", true);
+        assert!(res.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.x 200 OK\r\n"));
+        assert!(res.ends_with("\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn sends_date_and_configured_server() {
+        let mut pool = echo_pool();
+        pool.server_header = Some(String::from("hermitcrab"));
+        let res = round_trip(&mut pool, b"POST /echo HTTP/1.1\r\nContent-Length: 2\r\n\r\nhi", false);
+        assert!(res.contains("\r\nDate: "));
+        assert!(res.contains("\r\nServer: hermitcrab\r\n"));
+        let res = round_trip(&mut pool, b"BREW / HTTP/1.1\r\n\r\n", false);
+        assert!(res.contains("\r\nServer: hermitcrab\r\n"));
+    }
+
+    #[test]
+    fn exposes_connection_info() {
+        let mut pool = LinePool::new(1);
+        pool.http_muxer.add_handler(http::Method::GET, "/whoami", |req: &mut http::Req, res: &mut http::Res| {
+            let peer = req.peer_addr().map(|a| a.ip().to_string()).unwrap_or_default();
+            res.text(&format!("{} #{} n={} tls={}", peer, req.connection_id() > 0, req.request_count(), req.is_tls())).unwrap();
+        });
+        let res = round_trip(&mut pool, b"GET /whoami HTTP/1.1\r\n\r\n", false);
+        assert!(res.ends_with("\r\n\r\n127.0.0.1 #true n=1 tls=false"));
+    }
+
+    #[test]
+    fn reads_proxy_header_from_trusted_peers() {
+        let mut pool = LinePool::new(1);
+        pool.http_muxer.add_handler(http::Method::GET, "/whoami", |req: &mut http::Req, res: &mut http::Res| {
+            res.text(&req.client_addr().map(|a| a.to_string()).unwrap_or_default()).unwrap();
+        });
+        pool.proxy_protocol = Some(http::ProxyProtocol::new(vec!["127.0.0.0/8".parse().unwrap()]));
+        let res = round_trip(&mut pool, b"PROXY TCP4 192.0.2.1 127.0.0.1 56324 80\r\nGET /whoami HTTP/1.1\r\n\r\n", false);
+        assert!(res.ends_with("\r\n\r\n192.0.2.1:56324"));
+        // a trusted peer must send the header
+        assert_eq!(round_trip(&mut pool, b"GET /whoami HTTP/1.1\r\n\r\n", false), "");
+
+        pool.proxy_protocol = Some(http::ProxyProtocol::new(vec!["10.0.0.0/8".parse().unwrap()]));
+        let res = round_trip(&mut pool, b"PROXY TCP4 192.0.2.1 127.0.0.1 56324 80\r\nGET /whoami HTTP/1.1\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn rejects_expectations_early() {
+        let res = round_trip(&mut echo_pool(), b"POST /echo HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 9\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 413 Payload Too Large\r\n"));
+
+        let res = round_trip(&mut echo_pool(), b"POST /missing HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 404 Not Found\r\n"));
+
+        let res = round_trip(&mut echo_pool(), b"POST /echo HTTP/1.1\r\nExpect: something-else\r\nContent-Length: 5\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 417 Expectation Failed\r\n"));
+    }
+
+    #[test]
+    fn answers_limit_violations() {
+        let mut pool = echo_pool();
+        pool.limits.max_request_line = 20;
+        pool.limits.max_header_count = 1;
+        let res = round_trip(&mut pool, b"GET /a-very-long-path HTTP/1.1\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 414 URI Too Long\r\n"));
+        let res = round_trip(&mut pool, b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 431 Request Header Fields Too Large\r\n"));
+        let res = round_trip(&mut pool, b"POST /echo HTTP/1.1\r\nContent-Length: 9\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 413 Payload Too Large\r\n"));
+    }
+
+    #[test]
+    fn answers_malformed_requests() {
+        let mut pool = echo_pool();
+        let res = round_trip(&mut pool, b"GET / HTTP/1.1\r\nno colon\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 400 Bad Request\r\n"));
+        assert!(res.ends_with("\r\n\r\nBad Request"));
+
+        pool.http_muxer.on_parse_error(|e: &http::ParseError, res: &mut http::Res| {
+            res.respond(format!("custom: {}", e).as_bytes()).unwrap();
+        });
+        let res = round_trip(&mut pool, b"BREW /pot HTTP/1.1\r\n\r\n", false);
+        assert!(res.starts_with("HTTP/1.x 501 Not Implemented\r\n"));
+        assert!(res.ends_with("\r\n\r\ncustom: ParseError::UnknownMethod: \"BREW\""));
+    }
+}
@@ -52,7 +52,56 @@ impl Server {
         self.pool.http_muxer.filter(m)
     }
 
+    pub fn on_parse_error(&mut self, h: impl Fn(&http::ParseError, &mut http::Res) + Send + Sync + 'static) {
+        self.pool.http_muxer.on_parse_error(h)
+    }
+
+    /// Requests declaring a larger `Content-Length` are answered with `413` before the body is read.
+    pub fn set_max_body_size(&mut self, max: u64) {
+        self.pool.limits.max_body_size = Some(max);
+    }
+
+    pub fn set_limits(&mut self, limits: http::Limits) {
+        self.pool.limits = limits;
+    }
+
+    /// Expects a PROXY protocol (v1 or v2) header at the start of connections from `trusted` peers
+    /// and reports the client address it carries through `Req::client_addr`. Connections from
+    /// trusted peers without a valid header are dropped.
+    pub fn enable_proxy_protocol(&mut self, trusted: Vec<http::Cidr>) {
+        self.pool.proxy_protocol = Some(http::ProxyProtocol::new(trusted));
+    }
+
+    /// Proxies whose `Forwarded`/`X-Forwarded-*` headers are believed by `Req::client_addr`,
+    /// `Req::scheme` and `Req::host`. Those headers from any other peer are ignored.
+    pub fn set_trusted_proxies(&mut self, trusted: Vec<http::Cidr>) {
+        self.pool.trusted_proxies = trusted;
+    }
+
+    /// Value of the `Server` header sent with every response, unless a handler sets its own.
+    /// No `Server` header is sent by default.
+    pub fn set_server_header(&mut self, server: Option<&str>) {
+        self.pool.server_header = server.map(str::to_string);
+    }
+
+    /// Serves static files ending in `.ext` as `content_type`, overriding the built-in table.
+    pub fn set_mime_type(&mut self, ext: &str, content_type: &str) {
+        self.pool.http_muxer.mime_types().insert(ext, content_type)
+    }
+
     pub fn serve_static(&mut self, prefix: &str, dir_path: &str) -> io::Result<()> {
         self.pool.http_muxer.serve_static(prefix, dir_path)
     }
+
+    pub fn serve_spa(&mut self, prefix: &str, dir_path: &str, fallback: &str) -> io::Result<()> {
+        self.pool.http_muxer.serve_spa(prefix, dir_path, fallback)
+    }
+
+    pub fn serve_static_with(&mut self, prefix: &str, dir_path: &str, options: http::StaticOptions) -> io::Result<()> {
+        self.pool.http_muxer.serve_static_with(prefix, dir_path, options)
+    }
+
+    pub fn serve_embedded(&mut self, prefix: &str, dir: &http::EmbeddedDir, options: http::StaticOptions) -> io::Result<()> {
+        self.pool.http_muxer.serve_embedded(prefix, dir, options)
+    }
 }
\ No newline at end of file